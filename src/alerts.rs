@@ -0,0 +1,385 @@
+// Threshold alerting: the rules of the [alerts] section are evaluated against
+// every sample. A rule watches the numeric fields matching its metric (see
+// v2::Stats::numeric_fields, `*` matches any single path segment such as a
+// fs_name) and each matching field goes through its own states:
+//   ok -> pending   the value crossed the threshold
+//   pending -> firing   ...and stayed there for the `for` duration
+//   firing -> ok   the value crossed back the `clear` value (hysteresis)
+// A field that is no longer reported (e.g. a filesystem that stopped growing
+// has no time to full) goes back to ok as well.
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::config::AlertRule;
+use crate::v2;
+
+// ------------------------------------------------------------------
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Ok,
+    Pending,
+    Firing,
+}
+
+// A change worth notifying
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertTransition {
+    Firing,
+    Resolved,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AlertEvent {
+    pub event: AlertTransition,
+    pub fingerprint: String,        // "<rule>/<metric>", the same for the firing and its resolution
+    pub hostname: String,
+    pub timestamp: u64,
+    pub alert: AlertStatus,
+    pub basic_stats: v2::BasicStats,    // of the sample that triggered the event
+}
+
+#[derive(Serialize, Clone)]
+pub struct AlertStatus {
+    pub rule: String,
+    pub metric: String,
+    pub state: AlertState,
+    pub value: f64,                 // last value seen
+    pub threshold: f64,
+    pub clear: f64,
+    pub since: u64,                 // unix timestamp of the last state change
+    pub last_evaluated: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Condition {
+    Above(f64),
+    Below(f64),
+}
+
+struct Rule {
+    name: String,
+    metric: Vec<String>,
+    condition: Condition,
+    clear: f64,
+    for_secs: u64,
+}
+
+impl Rule {
+    fn from_config(rule: &AlertRule) -> Result<Self, String> {
+        let condition = match (rule.above, rule.below) {
+            (Some(above), None) => Condition::Above(above),
+            (None, Some(below)) => Condition::Below(below),
+            _ => return Err(String::from("exactly one of `above` and `below` is needed")),
+        };
+        let clear = match (condition, rule.clear) {
+            (Condition::Above(threshold), Some(clear)) if clear > threshold => {
+                return Err(format!("`clear` ({}) cannot be above the threshold ({})", clear, threshold))
+            }
+            (Condition::Below(threshold), Some(clear)) if clear < threshold => {
+                return Err(format!("`clear` ({}) cannot be below the threshold ({})", clear, threshold))
+            }
+            (_, Some(clear)) => clear,
+            (Condition::Above(threshold), None) | (Condition::Below(threshold), None) => threshold,
+        };
+        let for_secs = match &rule.for_duration {
+            Some(duration) => parse_duration(duration)?,
+            None => 0,
+        };
+        Ok(Rule {
+            name: rule.name.clone(),
+            metric: rule.metric.split('.').map(String::from).collect(),
+            condition,
+            clear,
+            for_secs,
+        })
+    }
+
+    fn matches(&self, field: &str) -> bool {
+        let segments: Vec<&str> = field.split('.').collect();
+        segments.len() == self.metric.len()
+            && self.metric.iter().zip(segments).all(|(pattern, segment)| pattern == "*" || pattern == segment)
+    }
+
+    fn threshold(&self) -> f64 {
+        match self.condition {
+            Condition::Above(threshold) | Condition::Below(threshold) => threshold,
+        }
+    }
+
+    fn breached_by(&self, value: f64) -> bool {
+        match self.condition {
+            Condition::Above(threshold) => value > threshold,
+            Condition::Below(threshold) => value < threshold,
+        }
+    }
+
+    fn cleared_by(&self, value: f64) -> bool {
+        match self.condition {
+            Condition::Above(_) => value <= self.clear,
+            Condition::Below(_) => value >= self.clear,
+        }
+    }
+}
+
+// ------------------------------------------------------------------
+
+pub struct AlertEngine {
+    rules: Vec<Rule>,
+    alerts: BTreeMap<(String, String), AlertStatus>,
+}
+
+impl AlertEngine {
+    // Invalid rules are reported and left out
+    pub fn new(rules: &[AlertRule]) -> Self {
+        let mut compiled: Vec<Rule> = Vec::new();
+        for rule in rules {
+            match Rule::from_config(rule) {
+                Ok(r) => compiled.push(r),
+                Err(e) => eprintln!("[!] Alert rule `{}` ignored: {}", rule.name, e),
+            }
+        }
+        AlertEngine { rules: compiled, alerts: BTreeMap::new() }
+    }
+
+    // Returns the alerts that started firing or got resolved with this sample
+    pub fn evaluate(&mut self, stats: &v2::Stats) -> Vec<AlertEvent> {
+        let mut events: Vec<AlertEvent> = Vec::new();
+        let now = stats.timestamp;
+        let fields = stats.numeric_fields();
+        for rule in &self.rules {
+            // Fields of the rule that were watched before but are missing from this sample
+            let missing: Vec<String> = self
+                .alerts
+                .values()
+                .filter(|alert| alert.rule == rule.name && alert.state != AlertState::Ok)
+                .filter(|alert| !fields.iter().any(|(metric, _)| *metric == alert.metric))
+                .map(|alert| alert.metric.clone())
+                .collect();
+            for metric in missing {
+                if let Some(alert) = self.alerts.get_mut(&(rule.name.clone(), metric.clone())) {
+                    let was_firing = alert.state == AlertState::Firing;
+                    alert.state = AlertState::Ok;
+                    alert.since = now;
+                    alert.last_evaluated = now;
+                    if was_firing {
+                        println!("[alert] `{}` resolved: {} is no longer reported", rule.name, metric);
+                        events.push(AlertEvent {
+                            event: AlertTransition::Resolved,
+                            fingerprint: format!("{}/{}", rule.name, metric),
+                            hostname: stats.hostname.clone(),
+                            timestamp: now,
+                            alert: alert.clone(),
+                            basic_stats: stats.basic_stats.clone(),
+                        });
+                    }
+                }
+            }
+
+            for (metric, value) in fields.iter().filter(|(metric, _)| rule.matches(metric)) {
+                let mut transition: Option<AlertTransition> = None;
+                let alert = self
+                    .alerts
+                    .entry((rule.name.clone(), metric.clone()))
+                    .or_insert_with(|| AlertStatus {
+                        rule: rule.name.clone(),
+                        metric: metric.clone(),
+                        state: AlertState::Ok,
+                        value: *value,
+                        threshold: rule.threshold(),
+                        clear: rule.clear,
+                        since: now,
+                        last_evaluated: now,
+                    });
+                alert.value = *value;
+                alert.last_evaluated = now;
+
+                if alert.state == AlertState::Ok && rule.breached_by(*value) {
+                    alert.state = AlertState::Pending;
+                    alert.since = now;
+                }
+                if alert.state == AlertState::Pending {
+                    if !rule.breached_by(*value) {
+                        alert.state = AlertState::Ok;
+                        alert.since = now;
+                    } else if now.saturating_sub(alert.since) >= rule.for_secs {
+                        alert.state = AlertState::Firing;
+                        alert.since = now;
+                        println!("[alert] `{}` firing: {} is {:.1} (threshold {})", rule.name, metric, value, rule.threshold());
+                        transition = Some(AlertTransition::Firing);
+                    }
+                } else if alert.state == AlertState::Firing && rule.cleared_by(*value) {
+                    alert.state = AlertState::Ok;
+                    alert.since = now;
+                    println!("[alert] `{}` resolved: {} is {:.1} (clear {})", rule.name, metric, value, rule.clear);
+                    transition = Some(AlertTransition::Resolved);
+                }
+
+                if let Some(event) = transition {
+                    events.push(AlertEvent {
+                        event,
+                        fingerprint: format!("{}/{}", rule.name, metric),
+                        hostname: stats.hostname.clone(),
+                        timestamp: now,
+                        alert: alert.clone(),
+                        basic_stats: stats.basic_stats.clone(),
+                    });
+                }
+            }
+        }
+        events
+    }
+
+    // Every field watched so far, pending and firing ones first
+    pub fn statuses(&self) -> Vec<AlertStatus> {
+        let mut statuses: Vec<AlertStatus> = self.alerts.values().cloned().collect();
+        statuses.sort_by_key(|alert| match alert.state {
+            AlertState::Firing => 0,
+            AlertState::Pending => 1,
+            AlertState::Ok => 2,
+        });
+        statuses
+    }
+}
+
+// ------------------------------------------------------------------
+
+// Parse a duration such as "90", "30s", "5m", "1h" or "1d" into seconds
+pub fn parse_duration(duration: &str) -> Result<u64, String> {
+    let duration = duration.trim();
+    let units: [(&str, u64); 4] = [("s", 1), ("m", 60), ("h", 3600), ("d", 86400)];
+    let (number, multiplier) = units
+        .iter()
+        .find_map(|(suffix, multiplier)| duration.strip_suffix(suffix).map(|number| (number, *multiplier)))
+        .unwrap_or((duration, 1));
+    number
+        .trim()
+        .parse::<u64>()
+        .map(|n| n * multiplier)
+        .map_err(|_| format!("invalid duration `{}`", duration))
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::sample;
+
+    fn rule(name: &str, metric: &str, above: Option<f64>, below: Option<f64>, clear: Option<f64>, for_duration: Option<&str>) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            metric: metric.to_string(),
+            above,
+            below,
+            clear,
+            for_duration: for_duration.map(String::from),
+        }
+    }
+
+
+    fn watched(name: &str, running: bool) -> v2::WatchedProcessStats {
+        v2::WatchedProcessStats {
+            name: name.to_string(),
+            running,
+            count: running as u64,
+            cpu_percent: 0.,
+            rss_bytes: 0,
+            memory_percent: None,
+            restarts: 0,
+        }
+    }
+
+    fn transitions(events: &[AlertEvent]) -> Vec<(String, AlertTransition)> {
+        events.iter().map(|event| (event.fingerprint.clone(), event.event)).collect()
+    }
+
+    fn state(engine: &AlertEngine, metric: &str) -> Option<AlertState> {
+        engine.statuses().iter().find(|alert| alert.metric == metric).map(|alert| alert.state)
+    }
+
+    #[test]
+    fn fires_at_once_and_resolves_past_the_clear_value() {
+        let mut engine = AlertEngine::new(&[rule("ram", "basic_stats.ram_percent", Some(90.), None, Some(80.), None)]);
+        assert!(engine.evaluate(&sample(100, 85.)).is_empty());
+        assert!(state(&engine, "basic_stats.ram_percent") == Some(AlertState::Ok));
+
+        let events = engine.evaluate(&sample(110, 95.));
+        assert!(transitions(&events) == vec![(String::from("ram/basic_stats.ram_percent"), AlertTransition::Firing)]);
+        assert_eq!(events[0].alert.since, 110);
+        assert_eq!(events[0].basic_stats.ram_percent, Some(95.));
+
+        // Back under the threshold but not under the clear value: still firing
+        assert!(engine.evaluate(&sample(120, 85.)).is_empty());
+        assert!(state(&engine, "basic_stats.ram_percent") == Some(AlertState::Firing));
+
+        let events = engine.evaluate(&sample(130, 79.));
+        assert!(transitions(&events) == vec![(String::from("ram/basic_stats.ram_percent"), AlertTransition::Resolved)]);
+        assert!(state(&engine, "basic_stats.ram_percent") == Some(AlertState::Ok));
+    }
+
+    #[test]
+    fn stays_pending_for_the_duration() {
+        let mut engine = AlertEngine::new(&[rule("ram", "basic_stats.ram_percent", Some(90.), None, None, Some("1m"))]);
+        assert!(engine.evaluate(&sample(100, 95.)).is_empty());
+        assert!(state(&engine, "basic_stats.ram_percent") == Some(AlertState::Pending));
+
+        // A dip under the threshold starts the wait over
+        assert!(engine.evaluate(&sample(130, 85.)).is_empty());
+        assert!(state(&engine, "basic_stats.ram_percent") == Some(AlertState::Ok));
+        assert!(engine.evaluate(&sample(140, 95.)).is_empty());
+        assert!(engine.evaluate(&sample(190, 95.)).is_empty());
+        assert!(state(&engine, "basic_stats.ram_percent") == Some(AlertState::Pending));
+
+        let events = engine.evaluate(&sample(200, 95.));
+        assert!(transitions(&events) == vec![(String::from("ram/basic_stats.ram_percent"), AlertTransition::Firing)]);
+
+        // Without a clear value, the threshold itself resolves it
+        let events = engine.evaluate(&sample(210, 90.));
+        assert!(transitions(&events) == vec![(String::from("ram/basic_stats.ram_percent"), AlertTransition::Resolved)]);
+    }
+
+    #[test]
+    fn watches_every_field_matching_a_wildcard() {
+        let mut engine = AlertEngine::new(&[rule("down", "processes_stats.watched.*.running", None, Some(1.), None, None)]);
+        let mut stats = sample(100, 50.);
+        stats.processes_stats.watched = vec![watched("nginx", false), watched("sshd", true)];
+        let events = engine.evaluate(&stats);
+        assert!(transitions(&events) == vec![(String::from("down/processes_stats.watched.nginx.running"), AlertTransition::Firing)]);
+        assert!(state(&engine, "processes_stats.watched.sshd.running") == Some(AlertState::Ok));
+        assert_eq!(engine.statuses()[0].metric, "processes_stats.watched.nginx.running");
+
+        // A field that is no longer reported resolves
+        stats.timestamp = 110;
+        stats.processes_stats.watched = vec![watched("sshd", true)];
+        let events = engine.evaluate(&stats);
+        assert!(transitions(&events) == vec![(String::from("down/processes_stats.watched.nginx.running"), AlertTransition::Resolved)]);
+        assert!(state(&engine, "processes_stats.watched.nginx.running") == Some(AlertState::Ok));
+    }
+
+    #[test]
+    fn leaves_out_invalid_rules() {
+        let engine = AlertEngine::new(&[
+            rule("both", "basic_stats.ram_percent", Some(90.), Some(10.), None, None),
+            rule("neither", "basic_stats.ram_percent", None, None, None, None),
+            rule("clear above", "basic_stats.ram_percent", Some(90.), None, Some(95.), None),
+            rule("clear below", "basic_stats.ram_percent", None, Some(10.), Some(5.), None),
+            rule("bad for", "basic_stats.ram_percent", Some(90.), None, None, Some("soon")),
+        ]);
+        assert!(engine.rules.is_empty());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("30s"), Ok(30));
+        assert_eq!(parse_duration("5m"), Ok(300));
+        assert_eq!(parse_duration(" 1h "), Ok(3600));
+        assert_eq!(parse_duration("2d"), Ok(172800));
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("-5m").is_err());
+    }
+}
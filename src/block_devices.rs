@@ -0,0 +1,122 @@
+// Block device collector: reads the cumulative I/O counters of /proc/diskstats
+// and turns the difference between two polls into rates
+// (https://www.kernel.org/doc/Documentation/ABI/testing/procfs-diskstats).
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::config::BlockDevicesConfig;
+use crate::v2::BlockDeviceStats;
+
+// ------------------------------------------------------------------
+
+const DISKSTATS_PATH: &str = "/proc/diskstats";
+const SECTOR_BYTES: f64 = 512.;
+
+// Devices left out when no device list is configured
+const IGNORED_PREFIXES: [&str; 3] = ["loop", "ram", "zram"];
+
+// ------------------------------------------------------------------
+
+// Counters of a device as seen in the previous poll
+#[derive(Clone, Copy)]
+struct Counters {
+    reads: u64,
+    sectors_read: u64,
+    ms_reading: u64,
+    writes: u64,
+    sectors_written: u64,
+    ms_writing: u64,
+    ms_doing_io: u64,
+}
+
+pub struct BlockDevicesCollector {
+    devices: Vec<String>,
+    polling_secs: usize,
+    last: HashMap<String, (Counters, Instant)>,
+}
+
+impl BlockDevicesCollector {
+    pub fn new(block_devices_config: &BlockDevicesConfig) -> Self {
+        BlockDevicesCollector {
+            devices: block_devices_config.devices.clone(),
+            polling_secs: block_devices_config.polling_secs,
+            last: HashMap::new(),
+        }
+    }
+
+    pub fn polling_secs(&self) -> usize {
+        self.polling_secs
+    }
+
+    pub fn collect(&mut self) -> Vec<BlockDeviceStats> {
+        let diskstats = match std::fs::read_to_string(DISKSTATS_PATH) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("[!] Could not read `{}`: {}", DISKSTATS_PATH, e);
+                return Vec::new();
+            }
+        };
+
+        let now = Instant::now();
+        let mut block_devices: Vec<BlockDeviceStats> = Vec::new();
+        for line in diskstats.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 14 {
+                continue;
+            }
+            let device = fields[2];
+            if !self.is_watched(device) {
+                continue;
+            }
+            let counter = |index: usize| fields[index].parse::<u64>().unwrap_or(0);
+            let counters = Counters {
+                reads: counter(3),
+                sectors_read: counter(5),
+                ms_reading: counter(6),
+                writes: counter(7),
+                sectors_written: counter(9),
+                ms_writing: counter(10),
+                ms_doing_io: counter(12),
+            };
+
+            let mut stats = BlockDeviceStats {
+                device: device.to_string(),
+                read_bytes_per_sec: None,
+                write_bytes_per_sec: None,
+                read_iops: None,
+                write_iops: None,
+                queue_time_ms: None,
+                util_percent: None,
+            };
+            if let Some((last, taken_at)) = self.last.get(device) {
+                let elapsed = now.duration_since(*taken_at).as_secs_f64();
+                // Counters go back to 0 when a device is removed and plugged back
+                if elapsed > 0.0 && counters.reads >= last.reads && counters.writes >= last.writes {
+                    let reads = (counters.reads - last.reads) as f64;
+                    let writes = (counters.writes - last.writes) as f64;
+                    stats.read_bytes_per_sec = Some(counters.sectors_read.saturating_sub(last.sectors_read) as f64 * SECTOR_BYTES / elapsed);
+                    stats.write_bytes_per_sec = Some(counters.sectors_written.saturating_sub(last.sectors_written) as f64 * SECTOR_BYTES / elapsed);
+                    stats.read_iops = Some(reads / elapsed);
+                    stats.write_iops = Some(writes / elapsed);
+                    let ms_waiting = (counters.ms_reading.saturating_sub(last.ms_reading) + counters.ms_writing.saturating_sub(last.ms_writing)) as f64;
+                    stats.queue_time_ms = Some(if reads + writes > 0.0 { ms_waiting / (reads + writes) } else { 0.0 });
+                    let ms_busy = counters.ms_doing_io.saturating_sub(last.ms_doing_io) as f64;
+                    stats.util_percent = Some((ms_busy / (elapsed * 1000.) * 100.).min(100.));
+                }
+            }
+            self.last.insert(device.to_string(), (counters, now));
+            block_devices.push(stats);
+        }
+        block_devices
+    }
+
+    // The configured devices, or every whole disk (partitions have no /sys/block entry)
+    fn is_watched(&self, device: &str) -> bool {
+        if !self.devices.is_empty() {
+            return self.devices.iter().any(|d| d == device);
+        }
+        !IGNORED_PREFIXES.iter().any(|prefix| device.starts_with(prefix))
+            && Path::new("/sys/block").join(device.replace('/', "!")).exists()
+    }
+}
@@ -1,6 +1,5 @@
 // Import the required dependencies.
 use serde_derive::{Serialize, Deserialize};
-use std::fs;
 use toml;
 //use log::{error, info, warn};
 
@@ -10,7 +9,10 @@ pub struct ConfigData {
     pub api_config: APIConfig,
     pub cmdn_config: Option<CMDNConfig>,
     pub file_systems_config: Option<FileSystemsConfig>,
+    pub block_devices_config: Option<BlockDevicesConfig>,
+    pub processes_config: Option<ProcessesConfig>,
     pub kubernetes_config: Option<KubernetesConfig>,
+    pub alerts: Option<AlertsConfig>,
 }
 
 // ------------------------------------------------------------------
@@ -20,14 +22,24 @@ pub struct APIConfig {
     pub listen_ip_addr: String,
     pub listen_port: String,
     pub history_depth: usize,
+    pub storage_path: Option<String>,               // directory where the history is persisted, leave it out to keep it in memory only
+    pub storage_retention_hours: Option<u64>,       // leave it out to keep the persisted history forever
 }
 
 impl APIConfig {
-    pub fn new(listen_ip_addr: String, listen_port: String, history_depth: usize) -> Self {
+    pub fn new(
+        listen_ip_addr: String,
+        listen_port: String,
+        history_depth: usize,
+        storage_path: Option<String>,
+        storage_retention_hours: Option<u64>,
+    ) -> Self {
         APIConfig {
             listen_ip_addr,
             listen_port,
             history_depth,
+            storage_path,
+            storage_retention_hours,
         }
     }
 }
@@ -44,10 +56,13 @@ pub struct CMDNConfig {
 	pub iface: String,
     pub get_temperature: bool,
     pub temperature_item: String,
+    #[serde(default)]
+    pub get_load: Option<bool>,                     // load average, uptime and process counts (false by default)
     pub polling_secs: usize,
 }
 
 impl CMDNConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         get_cpu: bool,
         get_mem: bool,
@@ -57,6 +72,7 @@ impl CMDNConfig {
         iface: String,
         get_temperature: bool,
         temperature_item: String,
+        get_load: Option<bool>,
         polling_secs: i32,
     ) -> Self {
         CMDNConfig {
@@ -68,7 +84,8 @@ impl CMDNConfig {
             iface,
             get_temperature,
             temperature_item,
-            polling_secs,
+            get_load,
+            polling_secs: polling_secs.try_into().unwrap(),
         }
     }
 }
@@ -80,45 +97,186 @@ impl CMDNConfig {
 pub struct FileSystemsConfig {
     pub file_systems: Vec<[String;2]>,
     pub polling_secs: usize,
+    pub forecast_window_hours: Option<u64>,         // history used to estimate the growth rate, defaults to 24
+    pub forecast_horizon_hours: Option<f64>,        // alert when a filesystem is expected to fill up within it
+    pub alert_not_mounted: Option<bool>,            // alert when a filesystem is not mounted
+    #[serde(default)]
+    pub discovery: Option<FileSystemsDiscovery>,
+}
+
+// Report every mounted disk found, besides the ones listed in file_systems.
+// Empty include lists let everything in, excludes are applied last.
+#[derive(Serialize, Deserialize,Clone)]
+pub struct FileSystemsDiscovery {
+    pub enabled: bool,
+    #[serde(default)]
+    pub include_fs_types: Vec<String>,              // e.g. ["ext4", "xfs", "btrfs", "vfat"]
+    #[serde(default)]
+    pub exclude_fs_types: Vec<String>,
+    #[serde(default)]
+    pub include_mount_points: Vec<String>,          // globs, e.g. ["/srv/*", "/media/**"]
+    #[serde(default)]
+    pub exclude_mount_points: Vec<String>,
+    pub include_devices: Option<String>,            // regex, e.g. "^/dev/(sd|mapper/)"
+    pub exclude_devices: Option<String>,
 }
 
 impl FileSystemsConfig {
-    pub fn new(file_systems: Vec<[String; 2]>, polling_secs: i32) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        file_systems: Vec<[String; 2]>,
+        polling_secs: i32,
+        forecast_window_hours: Option<u64>,
+        forecast_horizon_hours: Option<f64>,
+        alert_not_mounted: Option<bool>,
+        discovery: Option<FileSystemsDiscovery>,
+    ) -> Self {
         FileSystemsConfig {
             file_systems,
-            polling_secs,
+            polling_secs: polling_secs.try_into().unwrap(),
+            forecast_window_hours,
+            forecast_horizon_hours,
+            alert_not_mounted,
+            discovery,
         }
     }
 }
 
 // ------------------------------------------------------------------
 
+#[derive(Serialize, Deserialize,Clone)]
+pub struct BlockDevicesConfig {
+    #[serde(default)]
+    pub devices: Vec<String>,                       // e.g. ["sda", "nvme0n1"], leave it empty for every disk
+    pub polling_secs: usize,
+}
+
+impl BlockDevicesConfig {
+    pub fn new(devices: Vec<String>, polling_secs: i32) -> Self {
+        BlockDevicesConfig {
+            devices,
+            polling_secs: polling_secs.try_into().unwrap(),
+        }
+    }
+}
+
+// ------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize,Clone)]
+pub struct ProcessesConfig {
+    #[serde(default)]
+    pub top: usize,                                 // processes reported by CPU and by memory, 0 to report none
+    pub polling_secs: usize,
+    #[serde(default)]
+    pub watch: Vec<WatchedProcess>,
+    pub alert_not_running: Option<bool>,            // alert (process_not_running) when a watched process is gone (true by default)
+}
+
+impl ProcessesConfig {
+    pub fn new(top: usize, polling_secs: i32, watch: Vec<WatchedProcess>, alert_not_running: Option<bool>) -> Self {
+        ProcessesConfig {
+            top,
+            polling_secs: polling_secs.try_into().unwrap(),
+            watch,
+            alert_not_running,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone)]
+pub struct WatchedProcess {
+    pub name: String,                               // reported name, also matched against the process and executable names
+    pub cmdline: Option<String>,                    // regex matched against the command line instead
+}
+
+// ------------------------------------------------------------------
+
 #[derive(Serialize, Deserialize,Clone)]
 pub struct KubernetesConfig {
     pub master_nodes_ip: Vec<[String;2]>,
     pub worker_nodes_ip: Vec<[String;2]>,
     pub exclude_namespaces: Vec<String>,
     pub polling_secs: usize,
+    pub api_server_url: Option<String>,             // defaults to https://<first master node ip>:6443
+    pub bearer_token_file: Option<String>,
+    pub ca_cert_file: Option<String>,
+    pub insecure_skip_tls_verify: Option<bool>,
 }
 
 impl KubernetesConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         master_nodes_ip: Vec<[String; 2]>,
         worker_nodes_ip: Vec<[String; 2]>,
         exclude_namespaces: Vec<String>,
         polling_secs: i32,
+        api_server_url: Option<String>,
+        bearer_token_file: Option<String>,
+        ca_cert_file: Option<String>,
+        insecure_skip_tls_verify: Option<bool>,
     ) -> Self {
         KubernetesConfig {
             master_nodes_ip,
             worker_nodes_ip,
             exclude_namespaces,
-            polling_secs,
+            polling_secs: polling_secs.try_into().unwrap(),
+            api_server_url,
+            bearer_token_file,
+            ca_cert_file,
+            insecure_skip_tls_verify,
         }
     }
 }
 
 // ------------------------------------------------------------------
 
+#[derive(Serialize, Deserialize,Clone)]
+pub struct AlertsConfig {
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub emails: Vec<EmailConfig>,
+}
+
+#[derive(Serialize, Deserialize,Clone)]
+pub struct AlertRule {
+    pub name: String,
+    pub metric: String,                             // e.g. "basic_stats.ram_percent" or "file_systems_stats.*.fs_used_percent"
+    pub above: Option<f64>,                         // fires when the value goes above...
+    pub below: Option<f64>,                         // ...or below the threshold
+    pub clear: Option<f64>,                         // value to cross back before resolving, defaults to the threshold
+    #[serde(rename = "for")]
+    pub for_duration: Option<String>,               // "30s", "5m", "1h"... defaults to firing at once
+}
+
+#[derive(Serialize, Deserialize,Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Template with the {{event}}, {{fingerprint}}, {{hostname}}, {{timestamp}}, {{rule}}, {{metric}},
+    // {{value}}, {{threshold}}, {{clear}} and {{since}} placeholders, defaults to the JSON event
+    pub body: Option<String>,
+    pub content_type: Option<String>,               // defaults to application/json
+    pub retries: Option<u32>,                       // defaults to 3
+    pub backoff_secs: Option<u64>,                  // wait before the first retry, doubled on each one, defaults to 2
+}
+
+#[derive(Serialize, Deserialize,Clone)]
+pub struct EmailConfig {
+    pub smtp_server: String,
+    pub smtp_port: Option<u16>,                     // defaults to 587 for starttls, 465 for tls and 25 for plain
+    pub security: Option<String>,                   // "starttls" (default), "tls" or "plain"
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    pub retries: Option<u32>,
+    pub backoff_secs: Option<u64>,
+}
+
+// ------------------------------------------------------------------
+
 #[derive(Serialize, Deserialize,Clone)]
 pub struct DescrValuePair {
     pub description: String,
@@ -161,9 +319,10 @@ pub fn read_config(toml_filename: &str) -> ConfigData{
         }
     };
 
-    return config_data;
+    config_data
 }
 
+#[allow(dead_code)]
 fn write_config(filename: &str,configdata: &ConfigData){
     let toml_string = toml::to_string(configdata)
         .expect("\n[!] Could not encode TOML value")
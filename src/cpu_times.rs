@@ -0,0 +1,69 @@
+// CPU time split: reads the aggregated `cpu` line of /proc/stat and turns
+// the difference between two polls into the share of time spent in each mode
+// (https://www.kernel.org/doc/html/latest/filesystems/proc.html).
+use crate::v2::BasicStats;
+
+// ------------------------------------------------------------------
+
+const STAT_PATH: &str = "/proc/stat";
+
+// user, nice, system, idle, iowait, irq, softirq, steal (guest time is
+// already accounted in user and nice)
+const MODES: usize = 8;
+
+// ------------------------------------------------------------------
+
+pub struct CpuTimesCollector {
+    last: Option<[u64; MODES]>,
+}
+
+impl CpuTimesCollector {
+    // The ticks read now are the baseline of the first sample
+    pub fn new() -> Self {
+        CpuTimesCollector { last: read_cpu_ticks().ok() }
+    }
+
+    // Fill the cpu_<mode>_percent fields (left null when there is no baseline yet)
+    pub fn collect(&mut self, basic_stats: &mut BasicStats) {
+        let ticks = match read_cpu_ticks() {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("[!] Could not read `{}`: {}", STAT_PATH, e);
+                return;
+            }
+        };
+        if let Some(last) = self.last {
+            let mut delta = [0_f64; MODES];
+            for (index, value) in delta.iter_mut().enumerate() {
+                *value = ticks[index].saturating_sub(last[index]) as f64;
+            }
+            let total: f64 = delta.iter().sum();
+            if total > 0.0 {
+                let percent = |index: usize| Some(delta[index] / total * 100.);
+                basic_stats.cpu_user_percent = percent(0);
+                basic_stats.cpu_nice_percent = percent(1);
+                basic_stats.cpu_system_percent = percent(2);
+                basic_stats.cpu_idle_percent = percent(3);
+                basic_stats.cpu_iowait_percent = percent(4);
+                basic_stats.cpu_irq_percent = percent(5);
+                basic_stats.cpu_softirq_percent = percent(6);
+                basic_stats.cpu_steal_percent = percent(7);
+            }
+        }
+        self.last = Some(ticks);
+    }
+}
+
+fn read_cpu_ticks() -> Result<[u64; MODES], String> {
+    let stat = std::fs::read_to_string(STAT_PATH).map_err(|e| e.to_string())?;
+    let line = stat
+        .lines()
+        .find(|line| line.starts_with("cpu "))
+        .ok_or_else(|| String::from("no aggregated cpu line"))?;
+    let mut ticks = [0_u64; MODES];
+    // Older kernels report fewer modes, the missing ones stay at 0
+    for (index, value) in line.split_whitespace().skip(1).take(MODES).enumerate() {
+        ticks[index] = value.parse::<u64>().map_err(|e| format!("invalid cpu ticks `{}`: {}", value, e))?;
+    }
+    Ok(ticks)
+}
@@ -0,0 +1,104 @@
+// Filesystem discovery: picks the mounted disks reported by sysinfo that pass
+// the include/exclude filters of [file_systems_config.discovery], so that new
+// USB disks or LVM volumes show up without editing the config. A discovered
+// filesystem is named after its mount point, with the dots replaced by `_` as
+// the metric paths (e.g. `file_systems_stats.<fs_name>.fs_used_percent`) are
+// split on them.
+use glob::Pattern;
+use regex::Regex;
+
+use crate::config::FileSystemsDiscovery;
+
+// ------------------------------------------------------------------
+
+// The fs_name to report a filesystem under, so that it stays one segment of the metric paths
+pub fn fs_metric_name(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+// ------------------------------------------------------------------
+
+pub struct Discovery {
+    include_fs_types: Vec<String>,
+    exclude_fs_types: Vec<String>,
+    include_mount_points: Vec<Pattern>,
+    exclude_mount_points: Vec<Pattern>,
+    include_devices: Option<Regex>,
+    exclude_devices: Option<Regex>,
+}
+
+impl Discovery {
+    pub fn new(discovery_config: &FileSystemsDiscovery) -> Result<Self, String> {
+        let patterns = |globs: &[String]| -> Result<Vec<Pattern>, String> {
+            globs
+                .iter()
+                .map(|glob| Pattern::new(glob).map_err(|e| format!("invalid mount point glob `{}`: {}", glob, e)))
+                .collect()
+        };
+        let regex = |expression: &Option<String>| -> Result<Option<Regex>, String> {
+            expression
+                .as_ref()
+                .map(|re| Regex::new(re).map_err(|e| format!("invalid device regex `{}`: {}", re, e)))
+                .transpose()
+        };
+        Ok(Discovery {
+            include_fs_types: discovery_config.include_fs_types.iter().map(|t| t.to_lowercase()).collect(),
+            exclude_fs_types: discovery_config.exclude_fs_types.iter().map(|t| t.to_lowercase()).collect(),
+            include_mount_points: patterns(&discovery_config.include_mount_points)?,
+            exclude_mount_points: patterns(&discovery_config.exclude_mount_points)?,
+            include_devices: regex(&discovery_config.include_devices)?,
+            exclude_devices: regex(&discovery_config.exclude_devices)?,
+        })
+    }
+
+    // (fs_name, mount point) of the disks that pass the filters, leaving out
+    // the `known` ones (the filesystems configured by hand)
+    pub fn discover(&self, disks: &sysinfo::Disks, known: impl Fn(&sysinfo::Disk) -> bool) -> Vec<[String; 2]> {
+        let mut discovered: Vec<[String; 2]> = Vec::new();
+        for disk in disks.list() {
+            let mount_point = disk.mount_point().to_string_lossy().to_string();
+            let fs_type = disk.file_system().to_string_lossy().to_lowercase();
+            let device = disk.name().to_string_lossy().to_string();
+
+            if known(disk) || discovered.iter().any(|[_, m]| *m == mount_point) {
+                continue;
+            }
+            let included = (self.include_fs_types.is_empty() || self.include_fs_types.contains(&fs_type))
+                && (self.include_mount_points.is_empty() || self.include_mount_points.iter().any(|p| p.matches(&mount_point)))
+                && self.include_devices.as_ref().is_none_or(|re| re.is_match(&device));
+            let excluded = self.exclude_fs_types.contains(&fs_type)
+                || self.exclude_mount_points.iter().any(|p| p.matches(&mount_point))
+                || self.exclude_devices.as_ref().is_some_and(|re| re.is_match(&device));
+            if included && !excluded {
+                discovered.push([fs_metric_name(&mount_point), mount_point]);
+            }
+        }
+        discovered
+    }
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2;
+
+    #[test]
+    fn keeps_the_fs_name_one_metric_path_segment() {
+        assert_eq!(fs_metric_name("backups"), "backups");
+        assert_eq!(fs_metric_name("/mnt/data.v2"), "/mnt/data_v2");
+
+        let stats = v2::Stats {
+            file_systems_stats: vec![serde_json::from_value(serde_json::json!({
+                "fs_name": fs_metric_name("data.v2"),
+                "fs_mount_point": "/srv/data.v2",
+                "fs_used_percent": 10.,
+            })).unwrap()],
+            ..v2::sample(1000, 50.)
+        };
+        let paths: Vec<String> = stats.numeric_fields().into_iter().map(|(path, _)| path).collect();
+        assert!(paths.contains(&String::from("file_systems_stats.data_v2.fs_used_percent")));
+        assert!(paths.iter().all(|path| !path.starts_with("file_systems_stats.") || path.split('.').count() == 3));
+    }
+}
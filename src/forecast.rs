@@ -0,0 +1,102 @@
+// Disk-full forecasting: a least squares line fitted through the used space
+// of a filesystem over the forecast window gives its growth rate, and the
+// time left until it reaches 100% at that rate.
+
+// ------------------------------------------------------------------
+
+// Points needed before a growth rate is worth reporting
+const MIN_POINTS: usize = 3;
+
+// ------------------------------------------------------------------
+
+pub struct Forecast {
+    pub growth_percent_per_hour: f64,
+    pub hours_to_full: Option<f64>,
+}
+
+// Fit (unix timestamp, used percent) points, the last one being the current value
+pub fn forecast(points: &[(u64, f64)]) -> Option<Forecast> {
+    if points.len() < MIN_POINTS {
+        return None;
+    }
+    let (first_ts, _) = points[0];
+    // Signed, the clock may have stepped back between two points
+    let hours: Vec<f64> = points.iter().map(|(ts, _)| (*ts as f64 - first_ts as f64) / 3600.).collect();
+    let count = points.len() as f64;
+    let mean_hours = hours.iter().sum::<f64>() / count;
+    let mean_percent = points.iter().map(|(_, percent)| percent).sum::<f64>() / count;
+
+    let mut covariance = 0.0;
+    let mut variance = 0.0;
+    for (h, (_, percent)) in hours.iter().zip(points) {
+        covariance += (h - mean_hours) * (percent - mean_percent);
+        variance += (h - mean_hours).powi(2);
+    }
+    // All the points were taken at the same time
+    if variance == 0.0 {
+        return None;
+    }
+
+    let growth_percent_per_hour = covariance / variance;
+    let (_, current_percent) = points[points.len() - 1];
+    let hours_to_full = if growth_percent_per_hour > 0.0 {
+        Some(((100. - current_percent) / growth_percent_per_hour).max(0.0))
+    } else {
+        None
+    };
+    Some(Forecast { growth_percent_per_hour, hours_to_full })
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn needs_a_few_points_over_time() {
+        assert!(forecast(&[]).is_none());
+        assert!(forecast(&[(0, 10.), (3600, 20.)]).is_none());
+        assert!(forecast(&[(3600, 10.), (3600, 20.), (3600, 30.)]).is_none());
+    }
+
+    #[test]
+    fn estimates_the_time_to_full() {
+        let fit = forecast(&[(0, 50.), (3600, 51.), (7200, 52.), (10800, 53.)]).unwrap();
+        assert!(close(fit.growth_percent_per_hour, 1.));
+        assert!(fit.hours_to_full.is_some_and(|hours| close(hours, 47.)));
+
+        // The line is fitted through noisy points, the time left counts from the current value
+        let fit = forecast(&[(0, 10.), (1800, 14.), (3600, 10.), (5400, 14.), (7200, 12.)]).unwrap();
+        assert!(close(fit.growth_percent_per_hour, 0.8));
+        assert!(fit.hours_to_full.is_some_and(|hours| close(hours, 110.)));
+    }
+
+    #[test]
+    fn reports_no_time_to_full_when_not_growing() {
+        let fit = forecast(&[(0, 40.), (3600, 40.), (7200, 40.)]).unwrap();
+        assert!(close(fit.growth_percent_per_hour, 0.));
+        assert!(fit.hours_to_full.is_none());
+
+        let fit = forecast(&[(0, 60.), (3600, 55.), (7200, 50.)]).unwrap();
+        assert!(close(fit.growth_percent_per_hour, -5.));
+        assert!(fit.hours_to_full.is_none());
+    }
+
+    #[test]
+    fn copes_with_a_clock_stepping_back() {
+        let fit = forecast(&[(7200, 52.), (0, 50.), (3600, 51.), (10800, 53.)]).unwrap();
+        assert!(close(fit.growth_percent_per_hour, 1.));
+        assert!(fit.hours_to_full.is_some_and(|hours| close(hours, 47.)));
+    }
+
+    #[test]
+    fn is_full_already_past_100_percent() {
+        let fit = forecast(&[(0, 98.), (3600, 99.), (7200, 100.5)]).unwrap();
+        assert_eq!(fit.hours_to_full, Some(0.));
+    }
+}
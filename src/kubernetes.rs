@@ -0,0 +1,455 @@
+// Kubernetes collector: queries the API server for the configured nodes
+// and builds a KubernetesStats block out of
+//   .- /api/v1/nodes/{node}                            (capacity)
+//   .- /api/v1/pods?fieldSelector=spec.nodeName={node} (running pods)
+//   .- /apis/metrics.k8s.io/v1beta1/nodes/{node}       (cpu & memory usage, needs metrics-server)
+//   .- /api/v1/nodes/{node}/proxy/stats/summary        (root filesystem & network, from the kubelet)
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use ureq::tls::{Certificate, RootCerts, TlsConfig};
+
+use crate::config::KubernetesConfig;
+use crate::v2::{BasicStats, KubernetesNodeStats, KubernetesStats};
+
+// ------------------------------------------------------------------
+
+const DEFAULT_API_SERVER_PORT: u16 = 6443;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+const CONNECT_TIMEOUT_SECS: u64 = 3;
+const DEFAULT_POLLING_SECS: u64 = 900;
+
+// ------------------------------------------------------------------
+
+// Network counters of a node as seen in the previous poll
+struct NetworkSample {
+    rx_bytes: u64,
+    tx_bytes: u64,
+    taken_at: Instant,
+}
+
+pub struct KubernetesCollector {
+    agent: ureq::Agent,
+    api_server_url: String,
+    bearer_token: Option<String>,
+    nodes: Vec<(String, [String; 2])>,
+    exclude_namespaces: Vec<String>,
+    polling_secs: usize,
+    last_network: HashMap<String, NetworkSample>,
+}
+
+impl KubernetesCollector {
+    pub fn new(kubernetes_config: &KubernetesConfig) -> Result<Self, String> {
+        let api_server_url = match &kubernetes_config.api_server_url {
+            Some(url) if !url.is_empty() => url.trim_end_matches('/').to_string(),
+            _ => match kubernetes_config.master_nodes_ip.first() {
+                Some(master) => format!("https://{}:{}", master[1], DEFAULT_API_SERVER_PORT),
+                None => return Err(String::from("no api_server_url and no master node configured")),
+            },
+        };
+
+        let bearer_token = match &kubernetes_config.bearer_token_file {
+            Some(token_file) if !token_file.is_empty() => match std::fs::read_to_string(token_file) {
+                Ok(token) => Some(token.trim().to_string()),
+                Err(e) => return Err(format!("could not read bearer token file `{}`: {}", token_file, e)),
+            },
+            _ => None,
+        };
+
+        let mut tls_config = TlsConfig::builder()
+            .disable_verification(kubernetes_config.insecure_skip_tls_verify.unwrap_or(false));
+        if let Some(ca_cert_file) = &kubernetes_config.ca_cert_file {
+            let pem = std::fs::read(ca_cert_file)
+                .map_err(|e| format!("could not read CA certificate `{}`: {}", ca_cert_file, e))?;
+            let ca_cert = Certificate::from_pem(&pem)
+                .map_err(|e| format!("could not parse CA certificate `{}`: {}", ca_cert_file, e))?;
+            tls_config = tls_config.root_certs(RootCerts::new_with_certs(&[ca_cert]));
+        }
+
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS)))
+            .timeout_connect(Some(Duration::from_secs(CONNECT_TIMEOUT_SECS)))
+            .tls_config(tls_config.build())
+            .build()
+            .into();
+
+        let mut nodes: Vec<(String, [String; 2])> = Vec::new();
+        for node in &kubernetes_config.master_nodes_ip {
+            nodes.push((String::from("master"), node.clone()));
+        }
+        for node in &kubernetes_config.worker_nodes_ip {
+            nodes.push((String::from("worker"), node.clone()));
+        }
+
+        Ok(KubernetesCollector {
+            agent,
+            api_server_url,
+            bearer_token,
+            nodes,
+            exclude_namespaces: kubernetes_config.exclude_namespaces.clone(),
+            polling_secs: kubernetes_config.polling_secs,
+            last_network: HashMap::new(),
+        })
+    }
+
+    pub fn api_server_url(&self) -> &str {
+        &self.api_server_url
+    }
+
+    // Poll the nodes on a thread of its own, so that a slow or unreachable API
+    // server never delays the host samples. The latest stats are published in
+    // the returned slot, empty until the first poll is over.
+    pub fn start(mut self) -> Arc<Mutex<Option<KubernetesStats>>> {
+        let latest: Arc<Mutex<Option<KubernetesStats>>> = Arc::new(Mutex::new(None));
+        let published = Arc::clone(&latest);
+        let polling = match self.polling_secs {
+            0 => Duration::from_secs(DEFAULT_POLLING_SECS),
+            secs => Duration::from_secs(secs as u64),
+        };
+        thread::spawn(move || loop {
+            let poll_start = Instant::now();
+            let stats = self.collect();
+            *published.lock().unwrap() = Some(stats);
+            thread::sleep(polling.saturating_sub(poll_start.elapsed()));
+        });
+        latest
+    }
+
+    // Poll every configured node. Nodes (or parts of them) that cannot be
+    // queried are still reported, with null values for what is missing.
+    pub fn collect(&mut self) -> KubernetesStats {
+        let mut node_stats: Vec<KubernetesNodeStats> = Vec::new();
+        for (node_role, node) in self.nodes.clone() {
+            node_stats.push(self.collect_node(&node_role, &node[0], &node[1]));
+        }
+        KubernetesStats { node_stats }
+    }
+
+    fn collect_node(&mut self, node_role: &str, node_name: &str, node_ip: &str) -> KubernetesNodeStats {
+        let mut node_basic_stats = BasicStats::default();
+        let mut node_pods: Vec<String> = Vec::new();
+        let mut node_pods_max: Option<u64> = None;
+
+        // Capacity, needed to turn the usage figures into percentages
+        let mut cpu_capacity: Option<f64> = None;
+        let mut memory_capacity: Option<f64> = None;
+        match self.get_json(&format!("/api/v1/nodes/{}", node_name)) {
+            Ok(node) => {
+                let capacity = &node["status"]["capacity"];
+                cpu_capacity = capacity["cpu"].as_str().and_then(parse_quantity);
+                memory_capacity = capacity["memory"].as_str().and_then(parse_quantity);
+                node_pods_max = capacity["pods"]
+                    .as_str()
+                    .and_then(parse_quantity)
+                    .map(|pods| pods as u64);
+            }
+            Err(e) => eprintln!("[!] Kubernetes: could not get node `{}`: {}", node_name, e),
+        }
+
+        match self.get_json(&format!("/api/v1/pods?fieldSelector=spec.nodeName%3D{}", node_name)) {
+            Ok(pods) => {
+                if let Some(items) = pods["items"].as_array() {
+                    for pod in items {
+                        let namespace = pod["metadata"]["namespace"].as_str().unwrap_or("");
+                        let name = pod["metadata"]["name"].as_str().unwrap_or("");
+                        let phase = pod["status"]["phase"].as_str().unwrap_or("");
+                        if self.exclude_namespaces.iter().any(|ns| ns == namespace) {
+                            continue;
+                        }
+                        // Completed and failed pods no longer take a slot on the node
+                        if phase == "Succeeded" || phase == "Failed" {
+                            continue;
+                        }
+                        node_pods.push(format!("{}/{}", namespace, name));
+                    }
+                }
+            }
+            Err(e) => eprintln!("[!] Kubernetes: could not list pods of node `{}`: {}", node_name, e),
+        }
+
+        match self.get_json(&format!("/apis/metrics.k8s.io/v1beta1/nodes/{}", node_name)) {
+            Ok(metrics) => {
+                let usage = &metrics["usage"];
+                let cpu_usage = usage["cpu"].as_str().and_then(parse_quantity);
+                let memory_usage = usage["memory"].as_str().and_then(parse_quantity);
+                if let (Some(used), Some(total)) = (cpu_usage, cpu_capacity) {
+                    if total > 0.0 {
+                        node_basic_stats.cpu_percent = Some(used / total * 100.);
+                    }
+                }
+                if let (Some(used), Some(total)) = (memory_usage, memory_capacity) {
+                    if total > 0.0 {
+                        node_basic_stats.ram_percent = Some(used / total * 100.);
+                        node_basic_stats.ram_used_bytes = Some(used as u64);
+                        node_basic_stats.ram_total_bytes = Some(total as u64);
+                    }
+                }
+            }
+            Err(e) => eprintln!("[!] Kubernetes: could not get metrics of node `{}`: {}", node_name, e),
+        }
+
+        match self.get_json(&format!("/api/v1/nodes/{}/proxy/stats/summary", node_name)) {
+            Ok(summary) => {
+                let node = &summary["node"];
+                if let (Some(used), Some(total)) = (node["fs"]["usedBytes"].as_f64(), node["fs"]["capacityBytes"].as_f64()) {
+                    if total > 0.0 {
+                        node_basic_stats.root_fs_percent = Some(used / total * 100.);
+                    }
+                }
+                if let (Some(total), Some(free)) = (node["fs"]["inodes"].as_u64(), node["fs"]["inodesFree"].as_u64()) {
+                    if total > 0 {
+                        let used = total.saturating_sub(free);
+                        node_basic_stats.root_fs_inodes_total = Some(total);
+                        node_basic_stats.root_fs_inodes_used = Some(used);
+                        node_basic_stats.root_fs_inodes_free = Some(free);
+                        node_basic_stats.root_fs_inodes_percent = Some(used as f64 / total as f64 * 100.);
+                    }
+                }
+                if let (Some(used), Some(available)) = (node["swap"]["swapUsageBytes"].as_f64(), node["swap"]["swapAvailableBytes"].as_f64()) {
+                    if used + available > 0.0 {
+                        node_basic_stats.swap_percent = Some(used / (used + available) * 100.);
+                        node_basic_stats.swap_used_bytes = Some(used as u64);
+                        node_basic_stats.swap_total_bytes = Some((used + available) as u64);
+                    }
+                }
+                let (rx_bytes, tx_bytes) = sum_interfaces(&node["network"]);
+                let now = Instant::now();
+                if let Some(last) = self.last_network.get(node_name) {
+                    let elapsed = now.duration_since(last.taken_at).as_secs_f64();
+                    if elapsed > 0.0 && rx_bytes >= last.rx_bytes && tx_bytes >= last.tx_bytes {
+                        node_basic_stats.net_down_kbps = Some(((rx_bytes - last.rx_bytes) as f64 * 8. / elapsed) / 1024.);
+                        node_basic_stats.net_up_kbps = Some(((tx_bytes - last.tx_bytes) as f64 * 8. / elapsed) / 1024.);
+                    }
+                }
+                self.last_network.insert(
+                    node_name.to_string(),
+                    NetworkSample { rx_bytes, tx_bytes, taken_at: now },
+                );
+            }
+            Err(e) => eprintln!("[!] Kubernetes: could not get kubelet summary of node `{}`: {}", node_name, e),
+        }
+
+        KubernetesNodeStats {
+            node_role: node_role.to_string(),
+            node_name: node_name.to_string(),
+            node_ip: node_ip.to_string(),
+            node_basic_stats,
+            node_pods,
+            node_pods_max,
+        }
+    }
+
+    fn get_json(&self, path: &str) -> Result<Value, String> {
+        let mut request = self
+            .agent
+            .get(&format!("{}{}", self.api_server_url, path))
+            .header("Accept", "application/json");
+        if let Some(token) = &self.bearer_token {
+            request = request.header("Authorization", &format!("Bearer {}", token));
+        }
+        let mut response = request.call().map_err(|e| e.to_string())?;
+        response.body_mut().read_json::<Value>().map_err(|e| e.to_string())
+    }
+}
+
+// ------------------------------------------------------------------
+
+// Add up the cumulative rx/tx bytes of every interface in a kubelet summary
+fn sum_interfaces(network: &Value) -> (u64, u64) {
+    let mut rx_bytes: u64 = 0;
+    let mut tx_bytes: u64 = 0;
+    match network["interfaces"].as_array() {
+        Some(interfaces) => {
+            for iface in interfaces {
+                rx_bytes += iface["rxBytes"].as_u64().unwrap_or(0);
+                tx_bytes += iface["txBytes"].as_u64().unwrap_or(0);
+            }
+        }
+        None => {
+            rx_bytes = network["rxBytes"].as_u64().unwrap_or(0);
+            tx_bytes = network["txBytes"].as_u64().unwrap_or(0);
+        }
+    }
+    (rx_bytes, tx_bytes)
+}
+
+// Parse a kubernetes resource quantity ("250m", "4", "16318436Ki", "1.5Gi", "1e3"...)
+pub fn parse_quantity(quantity: &str) -> Option<f64> {
+    let quantity = quantity.trim();
+    let suffixes: [(&str, f64); 15] = [
+        ("Ki", 1024_f64),
+        ("Mi", 1024_f64.powi(2)),
+        ("Gi", 1024_f64.powi(3)),
+        ("Ti", 1024_f64.powi(4)),
+        ("Pi", 1024_f64.powi(5)),
+        ("Ei", 1024_f64.powi(6)),
+        ("n", 1e-9),
+        ("u", 1e-6),
+        ("m", 1e-3),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+    for (suffix, multiplier) in suffixes {
+        if let Some(number) = quantity.strip_suffix(suffix) {
+            return number.parse::<f64>().ok().map(|n| n * multiplier);
+        }
+    }
+    quantity.parse::<f64>().ok()
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // A stand-in API server answering the four queries of node `k3s-01`, and
+    // 404 for anything else. The kubelet counters grow by 1 MiB per query.
+    fn api_server(token: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let rx_bytes = AtomicU64::new(0);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line.split_whitespace().nth(1).unwrap_or("").to_string();
+                let mut authorized = false;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    authorized |= header.trim().eq_ignore_ascii_case(&format!("authorization: Bearer {}", token));
+                }
+
+                let body = match path.as_str() {
+                    "/api/v1/nodes/k3s-01" => String::from(
+                        r#"{"status": {"capacity": {"cpu": "4", "memory": "8Gi", "pods": "110"}}}"#,
+                    ),
+                    "/api/v1/pods?fieldSelector=spec.nodeName%3Dk3s-01" => String::from(
+                        r#"{"items": [
+                            {"metadata": {"namespace": "default", "name": "web"}, "status": {"phase": "Running"}},
+                            {"metadata": {"namespace": "default", "name": "job"}, "status": {"phase": "Succeeded"}},
+                            {"metadata": {"namespace": "kube-system", "name": "coredns"}, "status": {"phase": "Running"}}
+                        ]}"#,
+                    ),
+                    "/apis/metrics.k8s.io/v1beta1/nodes/k3s-01" => String::from(
+                        r#"{"usage": {"cpu": "500m", "memory": "2Gi"}}"#,
+                    ),
+                    "/api/v1/nodes/k3s-01/proxy/stats/summary" => {
+                        let rx = rx_bytes.fetch_add(1024 * 1024, Ordering::SeqCst);
+                        format!(
+                            r#"{{"node": {{
+                                "fs": {{"usedBytes": 25, "capacityBytes": 100, "inodes": 1000, "inodesFree": 750}},
+                                "network": {{"interfaces": [{{"rxBytes": {}, "txBytes": 10}}, {{"rxBytes": 5, "txBytes": 10}}]}}
+                            }}}}"#,
+                            rx
+                        )
+                    }
+                    _ => String::new(),
+                };
+                let status = match (authorized, body.is_empty()) {
+                    (false, _) => "401 Unauthorized",
+                    (true, true) => "404 Not Found",
+                    (true, false) => "200 OK",
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        url
+    }
+
+    fn collector(api_server_url: String, token: &str) -> KubernetesCollector {
+        let token_file = std::env::temp_dir().join(format!("stats-exporter-kube-token-{}-{}", std::process::id(), token));
+        std::fs::write(&token_file, format!("{}\n", token)).unwrap();
+        let kubernetes_config = KubernetesConfig::new(
+            vec![[String::from("k3s-01"), String::from("10.0.0.1")]],
+            vec![[String::from("k3s-02"), String::from("10.0.0.2")]],
+            vec![String::from("kube-system")],
+            60,
+            Some(api_server_url),
+            Some(token_file.to_string_lossy().to_string()),
+            None,
+            None,
+        );
+        let collector = KubernetesCollector::new(&kubernetes_config).unwrap();
+        let _ = std::fs::remove_file(&token_file);
+        collector
+    }
+
+    #[test]
+    fn parses_quantities() {
+        assert_eq!(parse_quantity("4"), Some(4.));
+        assert_eq!(parse_quantity("250m"), Some(0.25));
+        assert_eq!(parse_quantity("16Ki"), Some(16384.));
+        assert_eq!(parse_quantity("1.5Gi"), Some(1.5 * 1024_f64.powi(3)));
+        assert_eq!(parse_quantity("2G"), Some(2e9));
+        assert_eq!(parse_quantity("1e3"), Some(1000.));
+        assert_eq!(parse_quantity(" 110 "), Some(110.));
+        assert!(parse_quantity("500000n").is_some_and(|cores| (cores - 0.0005).abs() < 1e-12));
+        assert_eq!(parse_quantity(""), None);
+        assert_eq!(parse_quantity("lots"), None);
+        assert_eq!(parse_quantity("Gi"), None);
+    }
+
+    #[test]
+    fn collects_the_configured_nodes() {
+        let mut collector = collector(api_server("s3cr3t"), "s3cr3t");
+        let stats = collector.collect();
+        assert_eq!(stats.node_stats.len(), 2);
+
+        let master = &stats.node_stats[0];
+        assert_eq!((master.node_role.as_str(), master.node_name.as_str(), master.node_ip.as_str()), ("master", "k3s-01", "10.0.0.1"));
+        assert_eq!(master.node_pods, vec![String::from("default/web")]);
+        assert_eq!(master.node_pods_max, Some(110));
+        assert_eq!(master.node_basic_stats.cpu_percent, Some(12.5));
+        assert_eq!(master.node_basic_stats.ram_percent, Some(25.));
+        assert_eq!(master.node_basic_stats.ram_total_bytes, Some(8 * 1024 * 1024 * 1024));
+        assert_eq!(master.node_basic_stats.root_fs_percent, Some(25.));
+        assert_eq!(master.node_basic_stats.root_fs_inodes_used, Some(250));
+        // No rate until the counters have been seen twice
+        assert_eq!(master.node_basic_stats.net_down_kbps, None);
+
+        // A node the API server does not know is still reported, without values
+        let worker = &stats.node_stats[1];
+        assert_eq!(worker.node_name, "k3s-02");
+        assert!(worker.node_pods.is_empty());
+        assert_eq!(worker.node_pods_max, None);
+        assert_eq!(worker.node_basic_stats.cpu_percent, None);
+
+        thread::sleep(Duration::from_millis(50));
+        let stats = collector.collect();
+        let net_down_kbps = stats.node_stats[0].node_basic_stats.net_down_kbps;
+        assert!(net_down_kbps.is_some_and(|kbps| kbps > 0.0));
+        assert_eq!(stats.node_stats[0].node_basic_stats.net_up_kbps, Some(0.));
+    }
+
+    #[test]
+    fn sends_the_bearer_token() {
+        let mut collector = collector(api_server("s3cr3t"), "wrong");
+        let master = &collector.collect().node_stats[0];
+        assert!(master.node_pods.is_empty());
+        assert_eq!(master.node_pods_max, None);
+        assert_eq!(master.node_basic_stats.cpu_percent, None);
+    }
+}
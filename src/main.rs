@@ -1,30 +1,52 @@
+mod alerts;
+mod block_devices;
 pub mod config;
-
-use log::{error, info, warn};
-
-use config::{read_config,ConfigData, APIConfig, CMDNConfig, FileSystemsConfig, KubernetesConfig};
+mod cpu_times;
+mod discovery;
+mod forecast;
+mod kubernetes;
+mod network;
+mod notifiers;
+mod processes;
+mod prometheus;
+mod rollup;
+mod storage;
+mod stream;
+mod v2;
+
+use alerts::{AlertEngine, AlertStatus};
+use config::{read_config,ConfigData, AlertRule, APIConfig, CMDNConfig, FileSystemsConfig, FileSystemsDiscovery, KubernetesConfig};
+use discovery::Discovery;
+use block_devices::BlockDevicesCollector;
+use cpu_times::CpuTimesCollector;
+use kubernetes::KubernetesCollector;
+use network::InterfaceSelector;
+use processes::{ProcessTable, ProcessesCollector};
+use notifiers::{Delivery, EmailNotifier, Notifier, Notifiers, WebhookNotifier};
+use rollup::Rollups;
+use storage::SegmentStore;
+use stream::{StreamQuery, Subscription};
 
 use serde::{Serialize,Deserialize};
-use serde_json::json;
 
+use std::convert::Infallible;
 use std::thread;
 use std::sync::{Arc,Mutex};
 use std::time;
-use std::fmt;
 
-use sysinfo::{
-    Components, Disks, Networks, System,
-};
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt};
+use tokio_stream::wrappers::BroadcastStream;
 
 use axum::{
-    extract::State,
+    extract::{Query, State, WebSocketUpgrade},
     //extract::{Path,Extension,State},
     // body::Body,
-    // http::StatusCode,
-    // response::{IntoResponse, Response},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    response::sse::{Event, KeepAlive, Sse},
     // routing::{delete, get, post, put},
     routing::get,
-    Router,
     Json,
 };
 
@@ -32,11 +54,24 @@ use axum::{
 
 const VERSION: &str = "1.0.20240315";
 
+// Sampling interval used when [cmdn_config] is absent or sets polling_secs to 0
+const DEFAULT_POLLING_SECS: i32 = 5;
+
+// History used to estimate the growth of the filesystems, unless configured
+const DEFAULT_FORECAST_WINDOW_HOURS: u64 = 24;
+
+// Samples buffered for each /stream client before it starts skipping them
+const STREAM_CHANNEL_CAPACITY: usize = 16;
+
 // ------------------------------------------------------------------
 
 
 #[derive(Serialize,Deserialize,Clone)]
 struct Stats {
+    timestamp: u64,
+    sequence: u64,
+    collection_secs: f64,
+    hostname: String,
     basic_stats: BasicStats,
     file_systems_stats:Vec<FileSystemStats>,
     kubernetes_stats:Vec<KubernetesStats>
@@ -58,6 +93,7 @@ struct FileSystemStats {
     fs_name:String,
     fs_mount_point: String,   // device in the form of  /dev/disk/by-uuid/2ec8b2d7-7ef5-4b4b-9a03-d19bfe4c76c0
     fs_used_percentage: String,
+    fs_status: String,        // "mounted" or "not mounted"
 }
 
 #[derive(Serialize,Deserialize,Clone)]
@@ -75,16 +111,130 @@ struct KubernetesNodeStats {
     node_pods_max: usize,
 }
 
+// State shared by the API handlers
+#[derive(Clone)]
+struct AppState {
+    stats_data: Arc<Mutex<Vec<v2::Stats>>>,
+    stats_tx: broadcast::Sender<v2::Stats>,
+    rollups: Arc<Mutex<Rollups>>,
+    alerts: Arc<Mutex<AlertEngine>>,
+    temp_item: String,
+    get_temperature: bool,
+}
 
 // ------------------------------------------------------------------
 
+// The history is kept in the typed v2 model, the string schema served on
+// /get-stats is derived from it. Values that were not collected keep the
+// placeholders the legacy schema always sent: zero, and "-1" for a configured
+// temperature sensor that was not found.
+fn legacy_decimal(value: Option<f64>) -> String {
+    format!("{:.1}", value.unwrap_or(0.0))
+}
 
-// ------------------------------------------------------------------
+fn legacy_integer(value: Option<f64>) -> String {
+    format!("{}", value.unwrap_or(0.0) as i64)
+}
+
+impl BasicStats {
+    fn from_v2(basic_stats: &v2::BasicStats, get_temperature: bool) -> Self {
+        BasicStats {
+            cpu: legacy_decimal(basic_stats.cpu_percent),
+            ram: legacy_decimal(basic_stats.ram_percent),
+            root_fs: legacy_decimal(basic_stats.root_fs_percent),
+            swap_fs: legacy_decimal(basic_stats.swap_percent),
+            net_down_kbps: legacy_integer(basic_stats.net_down_kbps),
+            net_up_kbps: legacy_integer(basic_stats.net_up_kbps),
+            temperature: match (basic_stats.temperature_celsius, get_temperature) {
+                (None, true) => String::from("-1"),
+                (temperature, _) => legacy_integer(temperature),
+            },
+        }
+    }
+}
+
+impl Stats {
+    fn from_v2(stats: &v2::Stats, get_temperature: bool) -> Self {
+        Stats {
+            timestamp: stats.timestamp,
+            sequence: stats.sequence,
+            collection_secs: stats.collection_secs,
+            hostname: stats.hostname.clone(),
+            basic_stats: BasicStats::from_v2(&stats.basic_stats, get_temperature),
+            file_systems_stats: stats.file_systems_stats.iter().map(|fs| FileSystemStats {
+                fs_name: fs.fs_name.clone(),
+                fs_mount_point: fs.fs_mount_point.clone(),
+                fs_used_percentage: legacy_decimal(fs.fs_used_percent),
+                fs_status: match fs.fs_mounted {
+                    Some(true) => String::from("mounted"),
+                    Some(false) => String::from("not mounted"),
+                    None => String::from(""),
+                },
+            }).collect(),
+            kubernetes_stats: stats.kubernetes_stats.iter().map(|kube| KubernetesStats {
+                node_stats: kube.node_stats.iter().map(|node| KubernetesNodeStats {
+                    node_role: node.node_role.clone(),
+                    node_name: node.node_name.clone(),
+                    node_ip: node.node_ip.clone(),
+                    node_basic_stats: BasicStats::from_v2(&node.node_basic_stats, false),
+                    node_pods: node.node_pods.clone(),
+                    node_pods_max: node.node_pods_max.unwrap_or(0) as usize,
+                }).collect(),
+            }).collect(),
+        }
+    }
+}
+
+// Query parameters accepted by /get-stats and /v2/get-stats
+#[derive(Deserialize)]
+struct StatsQuery {
+    since: Option<u64>,         // unix timestamp (inclusive)
+    until: Option<u64>,         // unix timestamp (inclusive)
+    limit: Option<usize>,
+    offset: Option<usize>,
+    latest: Option<bool>,       // only the most recent sample within since/until
+}
+
+// Query parameters accepted by /v2/get-rollups
+#[derive(Deserialize)]
+struct RollupQuery {
+    since: Option<u64>,         // unix timestamp
+    until: Option<u64>,         // unix timestamp
+    tier: Option<String>,       // 1m, 15m or 1h (picked from since/until by default)
+}
+
+// Select the samples of the history matching the query, oldest first
+fn select_stats<'a>(stats: &'a [v2::Stats], query: &StatsQuery) -> Vec<&'a v2::Stats> {
+    let mut selected: Vec<&v2::Stats> = stats
+        .iter()
+        .filter(|s| query.since.is_none_or(|since| s.timestamp >= since))
+        .filter(|s| query.until.is_none_or(|until| s.timestamp <= until))
+        .collect();
+
+    if query.latest.unwrap_or(false) {
+        return selected.pop().into_iter().collect();
+    }
+
+    selected
+        .into_iter()
+        .skip(query.offset.unwrap_or(0))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+// Seconds since the unix epoch
+fn now_secs() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
 
 // Add whitespace prepending a value
+#[allow(dead_code)]
 fn add_whitespace (str_to_format: String, chars_tot: u32) -> String{
     // Get the length of the passed string and calculate how many spaces to add
-    let char_num = str_to_format.as_bytes().len() as u32;
+    let char_num = str_to_format.len() as u32;
     let space_num = chars_tot - char_num;
 
     // Create a new string to add everything to
@@ -100,136 +250,302 @@ fn add_whitespace (str_to_format: String, chars_tot: u32) -> String{
 }
 
 // Get the average core usage
-fn get_cpu_use(req_sys: &sysinfo::System) -> f32{
+fn get_cpu_use(req_sys: &sysinfo::System) -> f64{
     // Put all of the core loads into a vector
-    let mut cpus: Vec<f32> = Vec::new();
-    for core in req_sys.cpus() { cpus.push(core.cpu_usage()); }
+    let mut cpus: Vec<f64> = Vec::new();
+    for core in req_sys.cpus() { cpus.push(core.cpu_usage() as f64); }
 
     // Get the average load
-    let cpu_tot: f32 = cpus.iter().sum();
-    let cpu_avg: f32 = cpu_tot / cpus.len() as f32;
+    let cpu_tot: f64 = cpus.iter().sum();
+    cpu_tot / cpus.len() as f64
+}
 
-    cpu_avg
+// Get the usage of every core
+fn get_cpu_cores_use(req_sys: &sysinfo::System) -> Vec<f64>{
+    req_sys.cpus().iter().map(|core| core.cpu_usage() as f64).collect()
 }
 
 // Divide the used RAM by the total RAM
-fn get_ram_use(req_sys: &sysinfo::System) -> f32{
-    (req_sys.used_memory() as f32) / (req_sys.total_memory() as f32) * 100.
+fn get_ram_use(req_sys: &sysinfo::System) -> Option<f64>{
+    if req_sys.total_memory() == 0 {
+        return None;
+    }
+    Some((req_sys.used_memory() as f64) / (req_sys.total_memory() as f64) * 100.)
 }
 
 // Divide the used swap by the total swap
-fn get_swp_use(req_sys: &sysinfo::System) -> f32{
-    (req_sys.used_swap() as f32) / (req_sys.total_swap() as f32) * 100.
+fn get_swp_use(req_sys: &sysinfo::System) -> Option<f64>{
+    if req_sys.total_swap() == 0 {
+        return None;
+    }
+    Some((req_sys.used_swap() as f64) / (req_sys.total_swap() as f64) * 100.)
 }
 
 // Divide the available space in  root filesystem by the total space
-fn get_root_use(req_disk: &sysinfo::Disks) -> f32{
-    let mut ret_value:f32 = 0.0;
-    for disk in req_disk.list(){
-        if disk.mount_point().to_str().unwrap() == "/" {
-            ret_value = ((disk.total_space()-disk.available_space()) as f32) / (disk.total_space() as f32) * 100.;
-        }
-    }
-    return ret_value;
+fn get_root_use(req_disk: &sysinfo::Disks) -> Option<f64>{
+    get_fs_use(req_disk, "/")
 }
 
-// Divide the available space in specified filesystem by the total space
-fn get_fs_use(req_disk: &sysinfo::Disks, mount_fs: &str) -> f32{
-    let mut ret_value:f32 = 0.0;
+// Get the size, usage and mount details of a filesystem, left empty when it is not mounted.
+// The filesystem is looked up by device when configured as one (/dev/..., UUID=..., LABEL=...
+// or PARTUUID=...), by mount path otherwise.
+fn get_fs_stats(req_disk: &sysinfo::Disks, fs_name: &str, mount_fs: &str) -> v2::FileSystemStats{
+    let mut fs_stats = v2::FileSystemStats{
+        fs_name: fs_name.to_string(),
+        fs_mount_point: mount_fs.to_string(),
+        fs_mounted: Some(false),
+        fs_used_percent: None,
+        fs_total_bytes: None,
+        fs_used_bytes: None,
+        fs_available_bytes: None,
+        fs_inodes_total: None,
+        fs_inodes_used: None,
+        fs_inodes_free: None,
+        fs_inodes_used_percent: None,
+        fs_type: None,
+        fs_device: None,
+        fs_read_only: None,
+        fs_removable: None,
+        fs_growth_percent_per_hour: None,
+        fs_hours_to_full: None,
+    };
+    let device = resolve_device(mount_fs);
     for disk in req_disk.list(){
-        if disk.mount_point().to_str().unwrap() == mount_fs {
-            ret_value = ((disk.total_space()-disk.available_space()) as f32) / (disk.total_space() as f32) * 100.;
+        if is_fs_match(disk, mount_fs, &device) {
+            let total = disk.total_space();
+            let available = disk.available_space();
+            if total > 0 {
+                fs_stats.fs_used_percent = Some(((total-available) as f64) / (total as f64) * 100.);
+            }
+            fs_stats.fs_total_bytes = Some(total);
+            fs_stats.fs_used_bytes = Some(total.saturating_sub(available));
+            fs_stats.fs_available_bytes = Some(available);
+            if let Some(inodes) = get_inodes(disk.mount_point().to_str().unwrap()) {
+                fs_stats.fs_inodes_total = Some(inodes.total);
+                fs_stats.fs_inodes_used = Some(inodes.used);
+                fs_stats.fs_inodes_free = Some(inodes.free);
+                fs_stats.fs_inodes_used_percent = Some(inodes.used_percent);
+            }
+            fs_stats.fs_type = Some(disk.file_system().to_string_lossy().to_string());
+            fs_stats.fs_device = Some(disk.name().to_string_lossy().to_string());
+            fs_stats.fs_read_only = is_read_only(disk.mount_point().to_str().unwrap());
+            fs_stats.fs_removable = Some(disk.is_removable());
+            fs_stats.fs_mounted = Some(true);
+            break;
         }
     }
-    return ret_value;
+    fs_stats
 }
 
-// Get the total network (down) usage
-fn get_tot_ntwk_dwn(req_net: &sysinfo::Networks, polling_secs: &i32) -> i32{
-    // Get the total bytes recieved by every network interface
-    let mut rcv_tot: Vec<i32> = Vec::new();
-    for (_interface_name, ntwk) in req_net.list() {
-        rcv_tot.push(ntwk.received() as i32);
+// Whether a disk is the configured filesystem, `device` being its resolved device if configured as one
+fn is_fs_match(disk: &sysinfo::Disk, mount_fs: &str, device: &Option<std::path::PathBuf>) -> bool{
+    match device {
+        Some(device) => std::fs::canonicalize(disk.name()).is_ok_and(|name| name == *device),
+        None => disk.mount_point().to_str().unwrap() == mount_fs,
     }
+}
 
-    // Total them and convert the bytes to KB
-    let ntwk_tot: i32 = rcv_tot.iter().sum();
-    let ntwk_processed = (((ntwk_tot*8)/polling_secs) / 1024) as i32;
+// Resolve a device given as /dev/..., UUID=..., LABEL=... or PARTUUID=... to its /dev node,
+// None when the value is a mount path (/dev/shm is one) or names a device that does not exist
+fn resolve_device(mount_fs: &str) -> Option<std::path::PathBuf>{
+    let links: [(&str, &str); 3] = [
+        ("UUID=", "/dev/disk/by-uuid/"),
+        ("LABEL=", "/dev/disk/by-label/"),
+        ("PARTUUID=", "/dev/disk/by-partuuid/"),
+    ];
+    let device = links
+        .iter()
+        .find_map(|(prefix, dir)| mount_fs.strip_prefix(prefix).map(|id| format!("{}{}", dir, id)))
+        .or_else(|| mount_fs.starts_with("/dev/").then(|| mount_fs.to_string()))?;
+    std::fs::canonicalize(device).ok().filter(|path| !path.is_dir())
+}
 
-    ntwk_processed
+// Inode counts of a mounted filesystem
+struct Inodes {
+    total: u64,
+    used: u64,
+    free: u64,
+    used_percent: f64,
 }
 
-// Get the total network (up) usage
-fn get_tot_ntwk_up(req_net: &sysinfo::Networks, polling_secs: &i32) -> i32{
-    // Get the total bytes sent by every network interface
-    let mut snd_tot: Vec<i32> = Vec::new();
-    for (_interface_name, ntwk) in req_net.list() {
-        snd_tot.push(ntwk.transmitted() as i32);
+// Get the inode counts of the filesystem mounted on a path with statvfs,
+// None on failure or when the filesystem has no fixed inode table (btrfs...)
+fn get_inodes(mount_fs: &str) -> Option<Inodes>{
+    let path = std::ffi::CString::new(mount_fs).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path is a valid C string and stat a writable statvfs struct
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 || stat.f_files == 0 {
+        return None;
     }
-
-    // Total them and convert the bytes to KB
-    let ntwk_tot: i32 = snd_tot.iter().sum();
-    let ntwk_processed = (((ntwk_tot*8)/polling_secs) / 1024) as i32;
-
-    ntwk_processed
+    let total = stat.f_files as u64;
+    let free = stat.f_ffree as u64;
+    let used = total.saturating_sub(free);
+    Some(Inodes { total, used, free, used_percent: used as f64 / total as f64 * 100. })
 }
 
-// Get the network (down)  usage for an interface
-fn get_iface_ntwk_dwn(req_net: &sysinfo::Networks, polling_secs: &i32, iface: &str) -> i32{
-    // Get the total bytes recieved by every network interface
-    let mut rcv_tot: Vec<i32> = Vec::new();
-    for (interface_name, ntwk) in req_net.list() {
-        if interface_name == iface {
-            //println!("{:?} rx:{} in {} secs --> {} KBps", interface_name,ntwk.received(),polling_secs,((ntwk.received() as i32 /polling_secs) / 1000) as i32 );
-            rcv_tot.push(ntwk.received() as i32);
+// Look up the mount options of a mount point in /proc/mounts (sysinfo does not report them)
+fn is_read_only(mount_fs: &str) -> Option<bool>{
+    let mounts = std::fs::read_to_string("/proc/mounts").ok()?;
+    let mut read_only: Option<bool> = None;
+    for line in mounts.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Spaces in mount points are escaped as \040, the last mount over a path wins
+        if fields.len() >= 4 && fields[1].replace("\\040", " ") == mount_fs {
+            read_only = Some(fields[3].split(',').any(|option| option == "ro"));
         }
     }
+    read_only
+}
 
-    // Total them and convert the bytes to KB
-    let ntwk_tot: i32 = rcv_tot.iter().sum();
-    let ntwk_processed = (((ntwk_tot*8)/polling_secs) / 1024) as i32;
-
-    ntwk_processed
+// Divide the available space in specified filesystem by the total space
+fn get_fs_use(req_disk: &sysinfo::Disks, mount_fs: &str) -> Option<f64>{
+    let mut ret_value: Option<f64> = None;
+    for disk in req_disk.list(){
+        if disk.mount_point().to_str().unwrap() == mount_fs && disk.total_space() > 0 {
+            ret_value = Some(((disk.total_space()-disk.available_space()) as f64) / (disk.total_space() as f64) * 100.);
+        }
+    }
+    ret_value
 }
 
-// Get the network (up) usage for an interface
-fn get_iface_ntwk_up(req_net: &sysinfo::Networks, polling_secs: &i32, iface: &str) -> i32{
-    // Get the total bytes sent by every network interface
-    let mut snd_tot: Vec<i32> = Vec::new();
+// Get the network usage and link details of every selected interface, sorted by name
+fn get_ntwk_ifaces(req_net: &sysinfo::Networks, elapsed_secs: f64, selector: &InterfaceSelector) -> Vec<v2::NetworkInterfaceStats>{
+    let per_sec = |count: u64| if elapsed_secs > 0.0 { count as f64 / elapsed_secs } else { 0.0 };
+    let mut ip_addresses = network::ip_addresses();
+    let mut ifaces: Vec<v2::NetworkInterfaceStats> = Vec::new();
     for (interface_name, ntwk) in req_net.list() {
-        if interface_name == iface {
-            //println!("{:?} rx:{} in {} secs --> {} KBps", interface_name,ntwk.transmitted(),polling_secs,((ntwk.transmitted() as i32 /polling_secs) / 1000) as i32 );
-            snd_tot.push(ntwk.transmitted() as i32);
+        if !selector.matches(interface_name) {
+            continue;
         }
-
+        let link = network::read_link(interface_name);
+        let mac_address = ntwk.mac_address();
+        // Convert the bytes received and sent since the last refresh to Kb
+        ifaces.push(v2::NetworkInterfaceStats {
+            iface: interface_name.clone(),
+            down_kbps: per_sec(ntwk.received()*8) / 1024.,
+            up_kbps: per_sec(ntwk.transmitted()*8) / 1024.,
+            rx_packets_per_sec: per_sec(ntwk.packets_received()),
+            tx_packets_per_sec: per_sec(ntwk.packets_transmitted()),
+            rx_bytes_total: ntwk.total_received(),
+            tx_bytes_total: ntwk.total_transmitted(),
+            rx_packets_total: ntwk.total_packets_received(),
+            tx_packets_total: ntwk.total_packets_transmitted(),
+            rx_errors_total: ntwk.total_errors_on_received(),
+            tx_errors_total: ntwk.total_errors_on_transmitted(),
+            rx_dropped_total: link.rx_dropped,
+            tx_dropped_total: link.tx_dropped,
+            operstate: link.operstate,
+            link_up: link.link_up,
+            mtu: link.mtu,
+            mac_address: (!mac_address.is_unspecified()).then(|| mac_address.to_string()),
+            ip_addresses: ip_addresses.remove(interface_name).unwrap_or_default(),
+        });
     }
-
-    // Total them and convert the bytes to KB
-    let ntwk_tot: i32 = snd_tot.iter().sum();
-    let ntwk_processed = (((ntwk_tot*8)/polling_secs) / 1024) as i32;
-
-    ntwk_processed
+    ifaces.sort_by(|a, b| a.iface.cmp(&b.iface));
+    ifaces
 }
 
 // Get the temperature of the CPU
-fn get_temp(req_comp: &sysinfo::Components, temp_item: &str) -> i32{
+fn get_temp(req_comp: &sysinfo::Components, temp_item: &str) -> Option<f64>{
     // For every component, if it's the CPU, put its temperature in variable to return
-    let mut wanted_temp: f32 = -1.;
+    let mut wanted_temp: Option<f64> = None;
     for comp in req_comp.list() {
-        //println!("{:?}", comp.label());
-        if comp.label() == temp_item { wanted_temp = comp.temperature();
+        if comp.label() == temp_item { wanted_temp = Some(comp.temperature() as f64);
         }
     }
 
-    wanted_temp as i32
+    wanted_temp
+}
+
+// Fill the load average, uptime and boot time
+fn get_load_stats(basic_stats: &mut v2::BasicStats){
+    let load_average = sysinfo::System::load_average();
+    basic_stats.load_average_1m = Some(load_average.one);
+    basic_stats.load_average_5m = Some(load_average.five);
+    basic_stats.load_average_15m = Some(load_average.fifteen);
+    basic_stats.uptime_secs = Some(sysinfo::System::uptime());
+    basic_stats.boot_time = Some(sysinfo::System::boot_time());
 }
 
 // ------------------------------------------------------------------
 
 // API HANDLER: get statistics
-async fn api_get_stats(State(stats_data): State<Arc<Mutex<Vec<Stats>>>>,) -> Json<Vec<Stats>> {
-    let stats = stats_data.lock().unwrap();
-    axum::Json(stats.to_vec())
+async fn api_get_stats(State(app_state): State<AppState>, Query(query): Query<StatsQuery>) -> Json<Vec<Stats>> {
+    let stats = app_state.stats_data.lock().unwrap();
+    axum::Json(select_stats(&stats, &query).into_iter().map(|stats| Stats::from_v2(stats, app_state.get_temperature)).collect())
+}
+
+// API HANDLER: get statistics (typed v2 schema)
+async fn api_get_stats_v2(State(app_state): State<AppState>, Query(query): Query<StatsQuery>) -> Json<Vec<v2::Stats>> {
+    let stats = app_state.stats_data.lock().unwrap();
+    axum::Json(select_stats(&stats, &query).into_iter().cloned().collect())
+}
+
+// API HANDLER: get the min/max/avg/p95 windows of the tier that best fits the requested range
+async fn api_get_rollups(State(app_state): State<AppState>, Query(query): Query<RollupQuery>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let rollups = app_state.rollups.lock().unwrap();
+    let (tier, window_secs, windows) = rollups
+        .query(query.tier.as_deref(), query.since, query.until, now_secs())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(axum::Json(serde_json::json!({
+        "tier": tier,
+        "window_secs": window_secs,
+        "windows": windows,
+    })))
+}
+
+// API HANDLER: get the state of every field watched by the alert rules
+async fn api_get_alerts(State(app_state): State<AppState>,) -> Json<Vec<AlertStatus>> {
+    axum::Json(app_state.alerts.lock().unwrap().statuses())
+}
+
+// API HANDLER: push every new sample as a Server-Sent Event, starting with the latest one
+async fn api_stream(State(app_state): State<AppState>, Query(query): Query<StreamQuery>)
+    -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let mut subscription = Subscription::from_query(&query).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    // Subscribe while holding the lock the samples are published under, so
+    // that none is missed or sent twice in between
+    let (receiver, latest) = {
+        let stats = app_state.stats_data.lock().unwrap();
+        (app_state.stats_tx.subscribe(), stats.last().cloned())
+    };
+
+    // Slow clients that lag behind the channel simply skip the samples they missed
+    let samples = tokio_stream::iter(latest)
+        .chain(BroadcastStream::new(receiver).filter_map(|sample| sample.ok()));
+    let events = samples.filter_map(move |sample| {
+        if !subscription.is_due(&sample) {
+            return None;
+        }
+        let event = Event::default()
+            .event("stats")
+            .id(sample.sequence.to_string())
+            .json_data(subscription.filter(&sample))
+            .unwrap_or_else(|_| Event::default().comment("unable to encode sample"));
+        Some(Ok(event))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// API HANDLER: push the new samples over a WebSocket, clients can change their subscription at runtime
+async fn api_ws(State(app_state): State<AppState>, Query(query): Query<StreamQuery>, ws: WebSocketUpgrade)
+    -> Result<impl IntoResponse, (StatusCode, String)> {
+    let subscription = Subscription::from_query(&query).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    // Subscribe under the lock, as /stream does
+    let (receiver, latest) = {
+        let stats = app_state.stats_data.lock().unwrap();
+        (app_state.stats_tx.subscribe(), stats.last().cloned())
+    };
+    Ok(ws.on_upgrade(move |socket| stream::serve_websocket(socket, receiver, latest, subscription)))
+}
+
+// API HANDLER: get the latest statistics in Prometheus text format
+async fn api_get_metrics(State(app_state): State<AppState>,) -> impl IntoResponse {
+    let stats = app_state.stats_data.lock().unwrap();
+    let body = prometheus::render_metrics(stats.last(), &app_state.temp_item);
+    ([(header::CONTENT_TYPE, prometheus::CONTENT_TYPE)], body)
 }
 
 // API HANDLER: Get the temperature items
@@ -246,7 +562,7 @@ async fn api_get_temp_items() -> Json<Vec<String>> {
 async fn api_get_ntwk_items() -> Json<Vec<String>> {
     let current_net = sysinfo::Networks::new_with_refreshed_list();
     let mut ret_vec:Vec<String> = Vec::new();
-    for (interface_name, _ntwk) in current_net.list() {
+    for interface_name in current_net.list().keys() {
         ret_vec.push(interface_name.to_string());
     }
     axum::Json(ret_vec)
@@ -254,99 +570,252 @@ async fn api_get_ntwk_items() -> Json<Vec<String>> {
 
 // ------------------------------------------------------------------
 
-fn build_stats( cmdn_polling_secs:i32,
-                file_systems_polling_secs:i32,
-                kubernetes_polling_secs:i32,
-                history_depth:usize,
-                iface:String,
-                temp_item:String,
-                file_systems:Vec<[String;2]>,
-                master_nodes_ip:Vec<[String;2]>,
-                worker_nodes_ip:Vec<[String;2]>,
-                exclude_namespaces:Vec<String>,
-                stats_data: Arc<Mutex<Vec<Stats>>>) {
-
-
-    println!("Building and refreshing stats every {} seconds keeping a history depth of {}",cmdn_polling_secs.to_string(),history_depth.to_string());
+#[allow(clippy::too_many_arguments)]
+fn build_stats( cmdn_config: CMDNConfig,
+                file_systems_config: FileSystemsConfig,
+                mut block_devices_collector: Option<BlockDevicesCollector>,
+                mut processes_collector: Option<ProcessesCollector>,
+                kubernetes_stats: Option<Arc<Mutex<Option<v2::KubernetesStats>>>>,
+                history_depth: usize,
+                mut segment_store: Option<SegmentStore>,
+                stats_data: Arc<Mutex<Vec<v2::Stats>>>,
+                stats_tx: broadcast::Sender<v2::Stats>,
+                rollups: Arc<Mutex<Rollups>>,
+                alerts: Arc<Mutex<AlertEngine>>,
+                notifiers: Notifiers) {
+
+    let cmdn_polling_secs: i32 = cmdn_config.polling_secs.try_into().unwrap();
+    let file_systems_polling_secs = file_systems_config.polling_secs;
+    let iface = cmdn_config.iface.clone();
+    let temp_item = cmdn_config.temperature_item.clone();
+    let file_systems = file_systems_config.file_systems.clone();
+    let forecast_window_secs = file_systems_config.forecast_window_hours.unwrap_or(DEFAULT_FORECAST_WINDOW_HOURS) * 3600;
+
+    // Only the enabled metrics are refreshed and published
+    let get_cpu = cmdn_config.get_cpu;
+    let get_mem = cmdn_config.get_mem;
+    let get_root_fs = cmdn_config.get_root_fs;
+    let get_swap_fs = cmdn_config.get_swap_fs;
+    let get_net = cmdn_config.get_net;
+    let get_temperature = cmdn_config.get_temperature && !temp_item.is_empty();
+    let get_load = cmdn_config.get_load.unwrap_or(false);
+
+    println!("Building and refreshing stats every {} seconds keeping a history depth of {}",cmdn_polling_secs,history_depth);
 
     let mut file_systems_refresh_cycles: u64 = 900;
-    let mut kubernetes_refresh_cycles: u64 = 900;
+    let mut block_devices_refresh_cycles: u64 = 900;
+    let mut processes_refresh_cycles: u64 = 900;
 
 
     if file_systems_polling_secs > 0 {
-        file_systems_refresh_cycles = ((60 as f32/cmdn_polling_secs as f32)*(file_systems_polling_secs as f32/60 as f32)) as u64;
+        file_systems_refresh_cycles = (((60_f32/cmdn_polling_secs as f32)*(file_systems_polling_secs as f32/60_f32)) as u64).max(1);
     }
-    if kubernetes_polling_secs > 0 {
-        kubernetes_refresh_cycles = ((60 as f32/cmdn_polling_secs as f32)*(kubernetes_polling_secs as f32/60 as f32)) as u64;
+    if let Some(collector) = &block_devices_collector {
+        if collector.polling_secs() > 0 {
+            block_devices_refresh_cycles = (((60_f32/cmdn_polling_secs as f32)*(collector.polling_secs() as f32/60_f32)) as u64).max(1);
+        }
+    }
+    if let Some(collector) = &processes_collector {
+        if collector.polling_secs() > 0 {
+            processes_refresh_cycles = (((60_f32/cmdn_polling_secs as f32)*(collector.polling_secs() as f32/60_f32)) as u64).max(1);
+        }
     }
 
 
     let mut loop_count: u64 = 0;
 
-    // Define a system that we will check
-    let mut current_sys = sysinfo::System::new_all();
-    let mut current_disks = sysinfo::Disks::new_with_refreshed_list();
-    let mut current_net = sysinfo::Networks::new_with_refreshed_list();
-    let mut current_comp: sysinfo::Components=sysinfo::Components::new();
+    // Disks picked up on top of the configured ones
+    let mut discovery: Option<Discovery> = None;
+    if let Some(discovery_config) = file_systems_config.discovery.as_ref().filter(|d| d.enabled) {
+        match Discovery::new(discovery_config) {
+            Ok(d) => discovery = Some(d),
+            Err(e) => eprintln!("[!] Filesystem discovery disabled: {}", e),
+        }
+    }
 
-    let is_file_systems = file_systems.len()>0;
-    let mut last_fs_usage : Vec<FileSystemStats> = Vec::new();
+    let is_file_systems = !file_systems.is_empty() || discovery.is_some();
 
-    if temp_item.len() >0 {
+    // Interfaces picked by the iface value
+    let mut iface_selector: Option<InterfaceSelector> = None;
+    if get_net {
+        match InterfaceSelector::parse(&iface) {
+            Ok(selector) => iface_selector = Some(selector),
+            Err(e) => eprintln!("[!] Network stats disabled: {}", e),
+        }
+    }
+    let is_disks = get_root_fs || is_file_systems;
+
+    // Define a system that we will check, loading only what is going to be refreshed
+    let mut current_sys = sysinfo::System::new();
+    let mut current_disks = sysinfo::Disks::new();
+    let mut current_net = sysinfo::Networks::new();
+    let mut current_comp: sysinfo::Components=sysinfo::Components::new();
+
+    let mut cpu_times: Option<CpuTimesCollector> = None;
+    if get_cpu {
+        current_sys.refresh_cpu();
+        cpu_times = Some(CpuTimesCollector::new());
+    }
+    if is_disks {
+        current_disks = sysinfo::Disks::new_with_refreshed_list();
+    }
+    // Rates are computed over the time actually elapsed between two refreshes
+    let mut net_refreshed_at = time::Instant::now();
+    let mut net_elapsed_secs: f64 = 0.0;
+    if get_net {
+        current_net = sysinfo::Networks::new_with_refreshed_list();
+    }
+    if get_temperature {
         current_comp = sysinfo::Components::new_with_refreshed_list();
     }
 
+    let mut last_fs_usage : Vec<v2::FileSystemStats> = Vec::new();
+    let mut last_block_devices_usage : Vec<v2::BlockDeviceStats> = Vec::new();
+    let mut last_processes_usage = v2::ProcessesStats::default();
+    let mut process_table: Option<ProcessTable> = None;
+    if get_load || processes_collector.is_some() {
+        process_table = Some(ProcessTable::new(processes_collector.is_some()));
+    }
+
+    // Sample metadata: carry on the sequence of a reloaded history
+    let hostname = sysinfo::System::host_name().unwrap_or_default();
+    let mut sequence: u64 = match stats_data.lock().unwrap().last() {
+        Some(last) => last.sequence + 1,
+        None => 0,
+    };
+
     loop
     {
-        {
-            let mut stats = stats_data.lock().unwrap();
+        let collection_start = time::Instant::now();
+        let timestamp = now_secs();
 
-            let mut fs_usage : Vec<FileSystemStats> = Vec::new();
+        // The kubernetes collector polls on its own thread, take its latest stats
+        let mut kube_usage : Vec<v2::KubernetesStats> = Vec::new();
+        if let Some(latest) = &kubernetes_stats {
+            kube_usage.extend(latest.lock().unwrap().clone());
+        }
 
-            let mut kube_usage : Vec<KubernetesStats> = Vec::new();
+        // Walk the process table before taking the lock as well, once for the
+        // process counts and the processes section
+        let mut basic_stats = v2::BasicStats::default();
+        let is_processes_cycle = processes_collector.is_some() && loop_count.is_multiple_of(processes_refresh_cycles);
+        if let Some(table) = process_table.as_mut() {
+            if get_load || is_processes_cycle {
+                table.refresh();
+            }
+            if get_load {
+                table.count(&mut basic_stats);
+            }
+        }
+        let mut processes_usage = v2::ProcessesStats::default();
+        if let (Some(collector), Some(table)) = (processes_collector.as_mut(), &process_table) {
+            if is_processes_cycle {
+                processes_usage = collector.collect(table);
+                last_processes_usage = processes_usage.clone();
+            } else {
+                processes_usage = last_processes_usage.clone();
+            }
+        }
+
+        let sample: v2::Stats;
+        {
+            let mut stats = stats_data.lock().unwrap();
 
+            let mut fs_usage : Vec<v2::FileSystemStats> = Vec::new();
+            let mut net_usage : Vec<v2::NetworkInterfaceStats> = Vec::new();
 
             // Refresh the system
-            current_sys.refresh_all();
-            current_disks.refresh();
-            current_net.refresh();
-
-            if temp_item.len() >0 {
+            if get_cpu {
+                current_sys.refresh_cpu_usage();
+            }
+            if get_mem || get_swap_fs {
+                current_sys.refresh_memory();
+            }
+            // The list itself is reloaded with the filesystems, to notice (un)mounted disks
+            let is_file_systems_cycle = is_file_systems && loop_count.is_multiple_of(file_systems_refresh_cycles);
+            if is_file_systems_cycle {
+                current_disks.refresh_list();
+            } else if is_disks {
+                current_disks.refresh();
+            }
+            if get_net {
+                current_net.refresh();
+                net_elapsed_secs = net_refreshed_at.elapsed().as_secs_f64();
+                net_refreshed_at = time::Instant::now();
+            }
+            if get_temperature {
                 current_comp.refresh();
             }
 
             // Call each function to get all the values we need
-            let cpu_avg = get_cpu_use(&current_sys);
-            let ram_prcnt = get_ram_use(&current_sys);
-            let root_prcnt = get_root_use(&current_disks);
-            let swp_prcnt = get_swp_use(&current_sys);
-            let mut temperature = 0;
-            if temp_item.len() >0 {
-                temperature = get_temp(&current_comp,&temp_item);
+            if get_cpu {
+                basic_stats.cpu_percent = Some(get_cpu_use(&current_sys));
+                basic_stats.cpu_cores_percent = Some(get_cpu_cores_use(&current_sys));
             }
-
-            let ntwk_dwn ;
-            let ntwk_up ;
-            if iface == "total" {
-                ntwk_dwn = get_tot_ntwk_dwn(&current_net,&cmdn_polling_secs);
-                ntwk_up = get_tot_ntwk_up(&current_net,&cmdn_polling_secs);
+            if let Some(collector) = cpu_times.as_mut() {
+                collector.collect(&mut basic_stats);
+            }
+            if get_mem {
+                basic_stats.ram_percent = get_ram_use(&current_sys);
+                basic_stats.ram_used_bytes = Some(current_sys.used_memory());
+                basic_stats.ram_total_bytes = Some(current_sys.total_memory());
+            }
+            if get_root_fs {
+                basic_stats.root_fs_percent = get_root_use(&current_disks);
+                if let Some(inodes) = get_inodes("/") {
+                    basic_stats.root_fs_inodes_total = Some(inodes.total);
+                    basic_stats.root_fs_inodes_used = Some(inodes.used);
+                    basic_stats.root_fs_inodes_free = Some(inodes.free);
+                    basic_stats.root_fs_inodes_percent = Some(inodes.used_percent);
+                }
+            }
+            if get_swap_fs {
+                basic_stats.swap_percent = get_swp_use(&current_sys);
+                basic_stats.swap_used_bytes = Some(current_sys.used_swap());
+                basic_stats.swap_total_bytes = Some(current_sys.total_swap());
+            }
+            if get_temperature {
+                basic_stats.temperature_celsius = get_temp(&current_comp,&temp_item);
             }
-            else{
-                ntwk_dwn = get_iface_ntwk_dwn(&current_net,&cmdn_polling_secs,&iface);
-                ntwk_up = get_iface_ntwk_up(&current_net,&cmdn_polling_secs,&iface);
+            if get_load {
+                get_load_stats(&mut basic_stats);
+            }
+
+            // The host rates add up the selected interfaces (null when none of them is there)
+            if let Some(selector) = &iface_selector {
+                net_usage = get_ntwk_ifaces(&current_net,net_elapsed_secs,selector);
+                if !net_usage.is_empty() {
+                    basic_stats.net_down_kbps = Some(net_usage.iter().map(|i| i.down_kbps).sum());
+                    basic_stats.net_up_kbps = Some(net_usage.iter().map(|i| i.up_kbps).sum());
+                }
             }
 
             if is_file_systems {
-                if (loop_count % file_systems_refresh_cycles) == 0 {
-                    for fs in file_systems.clone(){
-                        let fs_usage_percent= get_fs_use(&current_disks, &fs[1]);
-                        fs_usage.push(
-                            FileSystemStats{
-                                fs_name: fs[0].clone(),
-                                fs_mount_point: fs[1].clone(),
-                                fs_used_percentage: format! ("{:.1}",fs_usage_percent),
+                if is_file_systems_cycle {
+                    let mut watched_file_systems = file_systems.clone();
+                    if let Some(discovery) = &discovery {
+                        let devices: Vec<_> = file_systems.iter().map(|fs| resolve_device(&fs[1])).collect();
+                        watched_file_systems.extend(discovery.discover(&current_disks, |disk| {
+                            file_systems.iter().zip(&devices).any(|(fs, device)| is_fs_match(disk, &fs[1], device))
+                        }));
+                    }
+                    for fs in watched_file_systems{
+                        let mut fs_stats = get_fs_stats(&current_disks, &fs[0], &fs[1]);
+
+                        // Growth over the forecast window, from the rollups plus the current value
+                        if let Some(percent) = fs_stats.fs_used_percent {
+                            let mut points = rollups.lock().unwrap().series(
+                                &format!("file_systems_stats.{}.fs_used_percent", fs[0]),
+                                timestamp.saturating_sub(forecast_window_secs),
+                                timestamp);
+                            points.push((timestamp, percent));
+                            if let Some(fs_forecast) = forecast::forecast(&points) {
+                                fs_stats.fs_growth_percent_per_hour = Some(fs_forecast.growth_percent_per_hour);
+                                fs_stats.fs_hours_to_full = fs_forecast.hours_to_full;
                             }
-                        )
+                        }
+
+                        fs_usage.push(fs_stats)
                     }
                     last_fs_usage = fs_usage.clone();
                 } else {
@@ -354,42 +823,39 @@ fn build_stats( cmdn_polling_secs:i32,
                 }
             }
 
-            // if is_kubernetes {
-            //     if (loop_count % kubernetes_refresh_cycles) == 0 {
-            //         //println!("Refreshing filesystems usagen in cycle {}",loop_count);
-            //         for fs in file_systems.clone(){
-            //                 KubernetesStats{
-            //                     fs_name: fs[0].clone(),
-            //                     fs_mount_point: fs[1].clone(),
-            //                     fs_used_percentage: format! ("{:.1}",fs_usage_percent),
-            //                 }
-            //             )
-            //         }
-            //         last_kube_usage = kube_usage.clone();
-            //     } else {
-            //         kube_usage = last_kube_usage.clone();
-            //     }
-            // }
+            // Get block devices I/O rates
+            let mut block_devices_usage : Vec<v2::BlockDeviceStats> = Vec::new();
+            if let Some(collector) = block_devices_collector.as_mut() {
+                if loop_count.is_multiple_of(block_devices_refresh_cycles) {
+                    block_devices_usage = collector.collect();
+                    last_block_devices_usage = block_devices_usage.clone();
+                } else {
+                    block_devices_usage = last_block_devices_usage.clone();
+                }
+            }
 
             if stats.len() == history_depth {
                 stats.remove(0);
             }
 
-            stats.push(
-                Stats{
-                    basic_stats: BasicStats{
-                        cpu: format! ("{:.1}",cpu_avg),
-                        ram: format! ("{:.1}",ram_prcnt),
-                        root_fs: format! ("{:.1}",root_prcnt),
-                        swap_fs: format! ("{:.1}",swp_prcnt),
-                        net_down_kbps: format! ("{:.1}",ntwk_dwn),
-                        net_up_kbps: format! ("{:.1}",ntwk_up),
-                        temperature: format! ("{:.1}",temperature),
-                    },
-                    file_systems_stats: fs_usage.clone(),
-                    kubernetes_stats: kube_usage.clone(),
-                }
-            );
+            sample = v2::Stats{
+                timestamp,
+                sequence,
+                collection_secs: collection_start.elapsed().as_secs_f64(),
+                hostname: hostname.clone(),
+                basic_stats,
+                file_systems_stats: fs_usage.clone(),
+                network_stats: net_usage,
+                block_devices_stats: block_devices_usage,
+                processes_stats: processes_usage,
+                kubernetes_stats: kube_usage,
+            };
+            stats.push(sample.clone());
+
+            // Push it to the stream subscribers (failing only when there are none)
+            // before releasing the lock, so that a client subscribing under the lock
+            // gets it either as the latest sample or from the channel, never both
+            let _ = stats_tx.send(sample.clone());
 
             //Print stats vector
             // let mut msg: String;
@@ -399,9 +865,27 @@ fn build_stats( cmdn_polling_secs:i32,
             // }
             // println!("------------------------------------------------------------------------------------------------");
         }
+
+        // Persist the sample once the lock is released
+        if let Some(store) = segment_store.as_mut() {
+            if let Err(e) = store.append(&sample) {
+                eprintln!("[!] Could not persist stats sample: {}", e);
+            }
+        }
+
+        // Fold it into the downsampled tiers
+        rollups.lock().unwrap().add(&sample);
+
+        // Check it against the alert rules, notifying what changed
+        let alert_events = alerts.lock().unwrap().evaluate(&sample);
+        for event in &alert_events {
+            notifiers.send(event);
+        }
+
         // Wait sample_sec seconds
         thread::sleep(time::Duration::from_secs(cmdn_polling_secs.try_into().unwrap()));
         loop_count += 1;
+        sequence += 1;
     }
 }
 
@@ -424,11 +908,23 @@ async fn main() {
     let listen_ip_addr: String = config_data.api_config.listen_ip_addr;
     let listen_port: String = config_data.api_config.listen_port;
     let history_depth: usize = config_data.api_config.history_depth;
-
-    let API_config: APIConfig = APIConfig::new(
-        listen_ip_addr,
-        listen_port,
-        history_depth);
+    let storage_path: Option<String> = config_data.api_config.storage_path;
+    let storage_retention_hours: Option<u64> = config_data.api_config.storage_retention_hours;
+
+    let api_config: APIConfig = APIConfig::new(
+        listen_ip_addr.clone(),
+        listen_port.clone(),
+        history_depth,
+        storage_path,
+        storage_retention_hours);
+
+    let mut segment_store: Option<SegmentStore> = None;
+    if let Some(storage_path) = &api_config.storage_path {
+        match SegmentStore::open(storage_path, api_config.storage_retention_hours) {
+            Ok(store) => segment_store = Some(store),
+            Err(e) => eprintln!("[!] History will not be persisted: {}", e),
+        }
+    }
 
     let get_cpu: bool;
     let get_mem: bool;
@@ -436,6 +932,7 @@ async fn main() {
     let get_swap_fs: bool;
     let get_net: bool;
     let get_temperature: bool;
+    let get_load: bool;
 
     let iface: String;
     let iface_clone: String;
@@ -443,7 +940,7 @@ async fn main() {
     let temp_item: String;
     let temp_item_clone: String;
     let is_temp_item: bool;
-    let cmdn_polling_secs: i32;
+    let mut cmdn_polling_secs: i32;
 
 
     // Cpu, Mem, Disk, Network config values
@@ -454,6 +951,7 @@ async fn main() {
         get_swap_fs = config_data.cmdn_config.clone().unwrap().get_swap_fs;
         get_net = config_data.cmdn_config.clone().unwrap().get_net;
         get_temperature = config_data.cmdn_config.clone().unwrap().get_temperature;
+        get_load = config_data.cmdn_config.clone().unwrap().get_load.unwrap_or(false);
         cmdn_polling_secs = config_data.cmdn_config.clone().unwrap().polling_secs.try_into().unwrap();
 
         iface = config_data.cmdn_config.clone().unwrap().iface;
@@ -461,7 +959,7 @@ async fn main() {
         is_iface_total = iface=="total";
         temp_item = config_data.cmdn_config.clone().unwrap().temperature_item;
         temp_item_clone = temp_item.clone();
-        is_temp_item = temp_item.len()>0;
+        is_temp_item = !temp_item.is_empty();
     }
     else {
         get_cpu = false;
@@ -470,7 +968,8 @@ async fn main() {
         get_swap_fs = false;
         get_net = false;
         get_temperature = false;
-        cmdn_polling_secs = 0;
+        get_load = false;
+        cmdn_polling_secs = DEFAULT_POLLING_SECS;
 
         iface = String::from("");
         iface_clone = iface.clone();
@@ -480,45 +979,92 @@ async fn main() {
         is_temp_item = false;
     }
 
+    if cmdn_polling_secs == 0 {
+        eprintln!("[!] Polling seconds can not be 0, using {} seconds", DEFAULT_POLLING_SECS);
+        cmdn_polling_secs = DEFAULT_POLLING_SECS;
+    }
+
     let cmdn_config: CMDNConfig = CMDNConfig::new(
         get_cpu,
         get_mem,
         get_root_fs,
         get_swap_fs,
         get_net,
-        iface,
+        iface.clone(),
         get_temperature,
-        temp_item,
+        temp_item.clone(),
+        Some(get_load),
         cmdn_polling_secs);
 
 
     let file_systems: Vec<[String;2]>;
     let is_file_systems: bool;
     let file_systems_polling_secs:i32;
+    let forecast_window_hours: Option<u64>;
+    let forecast_horizon_hours: Option<f64>;
+    let alert_not_mounted: bool;
+    let discovery: Option<FileSystemsDiscovery>;
 
     // File Systems config values
     if config_data.file_systems_config.is_some(){
         file_systems = config_data.file_systems_config
             .clone()
             .unwrap()
-            .file_systems;
-        is_file_systems = file_systems.len()>0;
+            .file_systems
+            .into_iter()
+            .map(|[fs_name, mount_point]| {
+                let metric_name = discovery::fs_metric_name(&fs_name);
+                if metric_name != fs_name {
+                    eprintln!("[!] Filesystem ´{}´ reported as ´{}´: fs_name cannot contain dots", fs_name, metric_name);
+                }
+                [metric_name, mount_point]
+            })
+            .collect();
+        discovery = config_data.file_systems_config.clone().unwrap().discovery.filter(|d| d.enabled);
+        is_file_systems = !file_systems.is_empty() || discovery.is_some();
         file_systems_polling_secs = config_data.file_systems_config.clone().unwrap().polling_secs.try_into().unwrap();
+        forecast_window_hours = config_data.file_systems_config.clone().unwrap().forecast_window_hours;
+        forecast_horizon_hours = config_data.file_systems_config.clone().unwrap().forecast_horizon_hours;
+        alert_not_mounted = config_data.file_systems_config.clone().unwrap().alert_not_mounted.unwrap_or(false);
     }
     else {
         file_systems = Vec::new();
         is_file_systems = false;
         file_systems_polling_secs = 0;
+        forecast_window_hours = None;
+        forecast_horizon_hours = None;
+        alert_not_mounted = false;
+        discovery = None;
     }
 
-    let filesystems_config: FileSystemsConfig = FileSystemsConfig::new(
-        file_systems,
-        file_systems_polling_secs);
+    let file_systems_config: FileSystemsConfig = FileSystemsConfig::new(
+        file_systems.clone(),
+        file_systems_polling_secs,
+        forecast_window_hours,
+        forecast_horizon_hours,
+        Some(alert_not_mounted),
+        discovery.clone());
+
+    // Block devices config values
+    let block_devices_collector: Option<BlockDevicesCollector> = config_data
+        .block_devices_config
+        .as_ref()
+        .map(BlockDevicesCollector::new);
+
+    // Processes config values
+    let processes_collector: Option<ProcessesCollector> = config_data
+        .processes_config
+        .as_ref()
+        .map(ProcessesCollector::new);
 
     let master_nodes_ip: Vec<[String;2]>;
     let worker_nodes_ip: Vec<[String;2]>;
     let exclude_namespaces: Vec<String>;
     let kubernetes_polling_secs:i32;
+    let api_server_url: Option<String>;
+    let bearer_token_file: Option<String>;
+    let ca_cert_file: Option<String>;
+    let insecure_skip_tls_verify: Option<bool>;
     let is_kubernetes: bool;
 
     // Kubernetes config values
@@ -536,21 +1082,41 @@ async fn main() {
             .unwrap()
             .exclude_namespaces;
         kubernetes_polling_secs = config_data.kubernetes_config.clone().unwrap().polling_secs.try_into().unwrap();
-        is_kubernetes = master_nodes_ip.len()>0;
+        api_server_url = config_data.kubernetes_config.clone().unwrap().api_server_url;
+        bearer_token_file = config_data.kubernetes_config.clone().unwrap().bearer_token_file;
+        ca_cert_file = config_data.kubernetes_config.clone().unwrap().ca_cert_file;
+        insecure_skip_tls_verify = config_data.kubernetes_config.clone().unwrap().insecure_skip_tls_verify;
+        is_kubernetes = !master_nodes_ip.is_empty();
     }
     else {
         master_nodes_ip = Vec::new();
         worker_nodes_ip = Vec::new();
         exclude_namespaces = Vec::new();
         kubernetes_polling_secs = 0;
+        api_server_url = None;
+        bearer_token_file = None;
+        ca_cert_file = None;
+        insecure_skip_tls_verify = None;
         is_kubernetes = false;
     }
 
     let kubernetes_config: KubernetesConfig = KubernetesConfig::new(
-        master_nodes_ip,
-        worker_nodes_ip,
-        exclude_namespaces,
-        kubernetes_polling_secs);
+        master_nodes_ip.clone(),
+        worker_nodes_ip.clone(),
+        exclude_namespaces.clone(),
+        kubernetes_polling_secs,
+        api_server_url,
+        bearer_token_file,
+        ca_cert_file,
+        insecure_skip_tls_verify);
+
+    let mut kubernetes_collector: Option<KubernetesCollector> = None;
+    if is_kubernetes {
+        match KubernetesCollector::new(&kubernetes_config) {
+            Ok(collector) => kubernetes_collector = Some(collector),
+            Err(e) => eprintln!("[!] Kubernetes stats disabled: {}", e),
+        }
+    }
 
 
     println!("------------------------------------------------------------------------");
@@ -561,7 +1127,14 @@ async fn main() {
     println!("------------------------------------------------------------------------");
     println!("  Listen ip address:         ´{}´", listen_ip_addr);
     println!("  Listen port:               ´{}´", listen_port);
-    println!("  History depth:             ´{}´", history_depth.to_string());
+    println!("  History depth:             ´{}´", history_depth);
+    if let Some(storage_path) = &api_config.storage_path {
+        println!("  Storage path:              ´{}´", storage_path);
+        match api_config.storage_retention_hours {
+            Some(hours) => println!("  Storage retention hours:   ´{}´", hours),
+            None => println!("  Storage retention hours:   ´unlimited´"),
+        }
+    }
     println!("------------------------------------------------------------------------");
     println!("  Get CPU stats:             ´{}´", get_cpu);
     println!("  Get MEMORY stats:          ´{}´", get_mem);
@@ -571,116 +1144,246 @@ async fn main() {
     println!("  Network Interface:         ´{}´", iface);
     println!("  Get Temperature stats:     ´{}´", get_temperature);
     println!("  Temperature item:          ´{}´", temp_item);
-    println!("  Polling seconds:           ´{}´", cmdn_polling_secs.to_string());
+    println!("  Get LOAD stats:            ´{}´", get_load);
+    println!("  Polling seconds:           ´{}´", cmdn_polling_secs);
     if is_file_systems{
         println!("------------------------------------------------------------------------");
         println!("  File Systems:              ");
-        for fs in file_systems{
+        for fs in file_systems.clone(){
             println!("                             ´{}´->´{}´",fs[0],fs[1]);
         }
+        if let Some(discovery) = &discovery {
+            println!("  Discover mounted disks:    ´true´");
+            println!("  Discovery fs types:        ´+{:?} -{:?}´", discovery.include_fs_types, discovery.exclude_fs_types);
+            println!("  Discovery mount points:    ´+{:?} -{:?}´", discovery.include_mount_points, discovery.exclude_mount_points);
+            println!("  Discovery devices:         ´+{} -{}´",
+                discovery.include_devices.as_deref().unwrap_or(""),
+                discovery.exclude_devices.as_deref().unwrap_or(""));
+        }
         println!("  File Systems Polling secs: ´{}´", file_systems_polling_secs);
+        println!("  Forecast window hours:     ´{}´", forecast_window_hours.unwrap_or(DEFAULT_FORECAST_WINDOW_HOURS));
+        if let Some(horizon) = forecast_horizon_hours {
+            println!("  Forecast horizon hours:    ´{}´", horizon);
+        }
+        println!("  Alert when not mounted:    ´{}´", alert_not_mounted);
     } else {
         println!("  No filesystem is configured to gather usage stats data");
     }
 
+    if let Some(block_devices_config) = &config_data.block_devices_config {
+        println!("------------------------------------------------------------------------");
+        if block_devices_config.devices.is_empty() {
+            println!("  Block devices:             ´all´");
+        } else {
+            println!("  Block devices:             ´{}´", block_devices_config.devices.join(","));
+        }
+        println!("  Block devices Polling secs:´{}´", block_devices_config.polling_secs);
+    }
+
+    if let Some(processes_config) = &config_data.processes_config {
+        println!("------------------------------------------------------------------------");
+        println!("  Top processes:             ´{}´", processes_config.top);
+        if !processes_config.watch.is_empty() {
+            println!("  Watched processes:         ");
+            for watched in &processes_config.watch {
+                match &watched.cmdline {
+                    Some(cmdline) => println!("                             ´{}´->´{}´", watched.name, cmdline),
+                    None => println!("                             ´{}´", watched.name),
+                }
+            }
+            println!("  Alert when not running:    ´{}´", processes_config.alert_not_running.unwrap_or(true));
+        }
+        println!("  Processes Polling secs:    ´{}´", processes_config.polling_secs);
+    }
+
     if is_kubernetes{
         println!("------------------------------------------------------------------------");
         println!("  Master Nodes:              ");
-        for masternodes in master_nodes_ip{
+        for masternodes in master_nodes_ip.clone(){
             println!("                             ´{}´->´{}´",masternodes[0],masternodes[1]);
         }
         println!("  Worker Nodes:              ");
-        for workernodes  in worker_nodes_ip{
+        for workernodes  in worker_nodes_ip.clone(){
             println!("                             ´{}´->´{}´",workernodes[0],workernodes[1]);
         }
         println!("  Excluded namespaces:              ");
-        for ex_namespaces in exclude_namespaces{
+        for ex_namespaces in exclude_namespaces.clone(){
             println!("                             ´{}´",ex_namespaces);
         }
+        if let Some(collector) = &kubernetes_collector {
+            println!("  API server:                ´{}´", collector.api_server_url());
+        }
         println!("  Kubernetes Polling secs:   ´{}´", kubernetes_polling_secs);
     } else {
         println!("  No kubernetes section is configured to gather usage stats data");
     }
 
+    if let Some(alerts_config) = &config_data.alerts {
+        println!("------------------------------------------------------------------------");
+        println!("  Alert rules:               ");
+        for rule in &alerts_config.rules {
+            println!("                             ´{}´->´{}´",rule.name,rule.metric);
+        }
+        if !alerts_config.webhooks.is_empty() {
+            println!("  Alert webhooks:            ");
+            for webhook in &alerts_config.webhooks {
+                println!("                             ´{}´",webhook.url);
+            }
+        }
+        if !alerts_config.emails.is_empty() {
+            println!("  Alert emails:              ");
+            for email in &alerts_config.emails {
+                println!("                             ´{}´->´{}´",email.smtp_server,email.to.join(", "));
+            }
+        }
+    }
+
     println!("------------------------------------------------------------------------\n");
 
-    let stats_data: Arc<Mutex<Vec<Stats>>> = Arc::new(Mutex::new(Vec::new()));
-    let stats_thread_data: Arc<Mutex<Vec<Stats>>> = Arc::clone(&stats_data);
+    // Rules that cannot be used are reported right below the banner
+    let mut alert_rules: Vec<AlertRule> = config_data.alerts.as_ref().map(|a| a.rules.clone()).unwrap_or_default();
+    if let (true, Some(horizon)) = (is_file_systems, forecast_horizon_hours) {
+        // Resolved once the forecast moves 20% beyond the horizon, so that it does not flap
+        alert_rules.push(AlertRule {
+            name: String::from("disk_full_forecast"),
+            metric: String::from("file_systems_stats.*.fs_hours_to_full"),
+            above: None,
+            below: Some(horizon),
+            clear: Some(horizon * 1.2),
+            for_duration: None,
+        });
+    }
+    if is_file_systems && alert_not_mounted {
+        alert_rules.push(AlertRule {
+            name: String::from("filesystem_not_mounted"),
+            metric: String::from("file_systems_stats.*.fs_mounted"),
+            above: None,
+            below: Some(1.),
+            clear: None,
+            for_duration: None,
+        });
+    }
+    if let Some(processes_config) = config_data.processes_config.as_ref().filter(|p| !p.watch.is_empty()) {
+        if processes_config.alert_not_running.unwrap_or(true) {
+            alert_rules.push(AlertRule {
+                name: String::from("process_not_running"),
+                metric: String::from("processes_stats.watched.*.running"),
+                above: None,
+                below: Some(1.),
+                clear: None,
+                for_duration: None,
+            });
+        }
+    }
+    let alert_engine = AlertEngine::new(&alert_rules);
+
+    let mut alert_notifiers: Vec<(Box<dyn Notifier>, Delivery)> = Vec::new();
+    if let Some(alerts_config) = &config_data.alerts {
+        for webhook in &alerts_config.webhooks {
+            alert_notifiers.push((
+                Box::new(WebhookNotifier::new(webhook)),
+                Delivery::new(webhook.retries, webhook.backoff_secs)));
+        }
+        for email in &alerts_config.emails {
+            match EmailNotifier::new(email) {
+                Ok(notifier) => alert_notifiers.push((
+                    Box::new(notifier),
+                    Delivery::new(email.retries, email.backoff_secs))),
+                Err(e) => eprintln!("[!] Alert emails through `{}` disabled: {}", email.smtp_server, e),
+            }
+        }
+    }
+    let notifiers = Notifiers::start(alert_notifiers);
+
+    // Reload the last samples persisted by a previous run
+    let mut initial_stats: Vec<v2::Stats> = Vec::new();
+    if let Some(store) = &segment_store {
+        initial_stats = store.load_latest(history_depth);
+        println!("Reloaded {} samples from the persisted history", initial_stats.len());
+    }
+
+    let stats_data: Arc<Mutex<Vec<v2::Stats>>> = Arc::new(Mutex::new(initial_stats));
+    let stats_thread_data: Arc<Mutex<Vec<v2::Stats>>> = Arc::clone(&stats_data);
+    let (stats_tx, _) = broadcast::channel::<v2::Stats>(STREAM_CHANNEL_CAPACITY);
+    let stats_thread_tx = stats_tx.clone();
+
+    // The rollups live next to the persisted history, if it could be opened
+    let rollup_storage_path = api_config.storage_path.as_deref().filter(|_| segment_store.is_some());
+    let rollups: Arc<Mutex<Rollups>> = Arc::new(Mutex::new(Rollups::new(rollup_storage_path)));
+    let rollups_thread_data: Arc<Mutex<Rollups>> = Arc::clone(&rollups);
+    let alerts: Arc<Mutex<AlertEngine>> = Arc::new(Mutex::new(alert_engine));
+    let alerts_thread_data: Arc<Mutex<AlertEngine>> = Arc::clone(&alerts);
+
+    let kubernetes_stats = kubernetes_collector.map(KubernetesCollector::start);
 
     std::thread::spawn( move || {
         build_stats(
-            cmdn_polling_secs,
-            file_systems_polling_secs,
-            kubernetes_polling_secs,
+            cmdn_config,
+            file_systems_config,
+            block_devices_collector,
+            processes_collector,
+            kubernetes_stats,
             history_depth,
-            iface,
-            temp_item,
-            file_systems,
-            master_nodes_ip,
-            worker_nodes_ip,
-            exclude_namespaces,
-            stats_thread_data);
+            segment_store,
+            stats_thread_data,
+            stats_thread_tx,
+            rollups_thread_data,
+            alerts_thread_data,
+            notifiers);
     });
 
-    let api_thread_data:Arc<Mutex<Vec<Stats>>> = Arc::clone(&stats_data);
-
-    let mut help: String = String::from("");
-    if is_iface_total {
-        if is_temp_item {
-            help = format!(
-"Hello from getStats! \n\n
-Currently building usage statistics for \n    .- cpu,\n    .- memory,\n    .- root filesystem,\n    .- swap,\n
-and\n    .-temperature sensor {} \n
-and\n    .-total bandwitdth (all interfaces)\n
-every {} seconds with a history depth of {} \n\n
-Use: \n    /get-stats url to acccess usage statistics\n    /get-ntwk-items url to get the names of the network interfaces available \n    /get-temp-items url to get the list of temperature sensors available",
-                temp_item_clone.to_string(),
-                cmdn_polling_secs.to_string(),
-                history_depth.to_string()
-            );
+    let api_thread_data = AppState {
+        stats_data: Arc::clone(&stats_data),
+        stats_tx,
+        rollups,
+        alerts,
+        temp_item: temp_item_clone.clone(),
+        get_temperature: get_temperature && is_temp_item,
+    };
+
+    let api_usage = "Use: \n    /get-stats url to acccess usage statistics (optional ?since=&until=<unix time>&limit=&offset=&latest=true)\n    /v2/get-stats url to acccess usage statistics as typed numeric values\n    /v2/get-rollups url to access 1m/15m/1h min/max/avg/p95 windows (optional ?since=&until=<unix time>&tier=)\n    /metrics url to scrape the latest usage statistics in Prometheus format\n    /alerts url to get the state of the alert rules\n    /stream url to receive every new sample as Server-Sent Events (optional ?sections=basic,filesystems,network,blockdevices,processes,kubernetes&fs_names=&interval_secs=)\n    /ws url to receive new samples over a WebSocket, subscribing to sections/filesystems at runtime (same optional parameters)\n    /get-ntwk-items url to get the names of the network interfaces available \n    /get-temp-items url to get the list of temperature sensors available";
+
+    // Only the enabled metrics are listed
+    let mut collected_items: Vec<String> = Vec::new();
+    if get_cpu { collected_items.push(String::from("cpu")); }
+    if get_mem { collected_items.push(String::from("memory")); }
+    if get_root_fs { collected_items.push(String::from("root filesystem")); }
+    if get_swap_fs { collected_items.push(String::from("swap")); }
+    if get_load { collected_items.push(String::from("load average, uptime and processes")); }
+    if get_temperature && is_temp_item { collected_items.push(format!("temperature sensor {}", temp_item_clone)); }
+    if get_net {
+        if is_iface_total {
+            collected_items.push(String::from("total bandwitdth (all interfaces)"));
         } else {
-            help = format!(
-"Hello from getStats! \n\n
-Currently building usage statistics for \n    .- cpu,\n    .- memory,\n    .- root filesystem,\n    .- swap,\n
-and total bandwitdth (all interfaces)\n
-every {} seconds with a history depth of {} \n\n
-Use: \n    /get-stats url to acccess usage statistics\n    /get-ntwk-items url to get the names of the network interfaces available \n    /get-temp-items url to get the list of temperature sensors available",
-                cmdn_polling_secs.to_string(),
-                history_depth.to_string()
-            );
+            collected_items.push(format!("bandwitdth on interfaces {}", iface_clone));
         }
-    } else {
-        if is_temp_item {
-            help = format!(
-"Hello from getStats! \n\n
-Currently building usage statistics for \n    .- cpu,\n    .- memory,\n    .- root filesystem,\n    .- swap,\n
-and\n    .-temperature sensor {}\n
-and\n    .-bandwitdth on interface {}\n
-every {} seconds with a history depth of {} \n\n
-Use: \n    /get-stats url to acccess usage statistics\n    /get-ntwk-items url to get the names of the network interfaces available \n    /get-temp-items url to get the list of temperature sensors available",
-                temp_item_clone.to_string(),
-                iface_clone.to_string(),
-                cmdn_polling_secs.to_string(),
-                history_depth.to_string()
-            );
-        } else {
-            help = format!(
+    }
+    let mut collected_list = String::new();
+    for item in collected_items {
+        collected_list.push_str(&format!("    .- {},\n", item));
+    }
+
+    let help: String = format!(
 "Hello from getStats! \n\n
-Currently building usage statistics for \n    .- cpu,\n    .- memory,\n    .- root filesystem,\n    .- swap,\n
-and\n    .-bandwitdth on interface {}\n
+Currently building usage statistics for \n{}
 every {} seconds with a history depth of {} \n\n
-Use: \n    /get-stats url to acccess usage statistics\n    /get-ntwk-items url to get the names of the network interfaces available \n    /get-temp-items url to get the list of temperature sensors available",
-                iface_clone.to_string(),
-                cmdn_polling_secs.to_string(),
-                history_depth.to_string()
-            );
-        }
-    }
+{}",
+        collected_list,
+        cmdn_polling_secs,
+        history_depth,
+        api_usage
+    );
 
     // API listener
     let app = axum::Router::new()
     .route("/", get( move || async { help }))
     .route("/get-stats", get(api_get_stats))
+    .route("/v2/get-stats", get(api_get_stats_v2))
+    .route("/v2/get-rollups", get(api_get_rollups))
+    .route("/alerts", get(api_get_alerts))
+    .route("/metrics", get(api_get_metrics))
+    .route("/stream", get(api_stream))
+    .route("/ws", get(api_ws))
     .route("/get-temp-items", get(api_get_temp_items))
     .route("/get-ntwk-items", get(api_get_ntwk_items))
     .with_state(api_thread_data);
@@ -695,4 +1398,60 @@ Use: \n    /get-stats url to acccess usage statistics\n    /get-ntwk-items url t
     //     .await
     //     .unwrap();
 
-}
\ No newline at end of file
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> Vec<v2::Stats> {
+        (0..10u64)
+            .map(|index| v2::Stats { sequence: index, ..v2::sample(1000 + index * 10, 50.) })
+            .collect()
+    }
+
+    fn query(since: Option<u64>, until: Option<u64>, limit: Option<usize>, offset: Option<usize>, latest: Option<bool>) -> StatsQuery {
+        StatsQuery { since, until, limit, offset, latest }
+    }
+
+    fn sequences(selected: Vec<&v2::Stats>) -> Vec<u64> {
+        selected.iter().map(|stats| stats.sequence).collect()
+    }
+
+    #[test]
+    fn selects_the_whole_history_by_default() {
+        let stats = history();
+        assert_eq!(sequences(select_stats(&stats, &query(None, None, None, None, None))), (0..10).collect::<Vec<u64>>());
+        assert!(select_stats(&[], &query(None, None, None, None, Some(true))).is_empty());
+    }
+
+    #[test]
+    fn selects_a_time_range() {
+        let stats = history();
+        assert_eq!(sequences(select_stats(&stats, &query(Some(1020), Some(1050), None, None, None))), vec![2, 3, 4, 5]);
+        assert_eq!(sequences(select_stats(&stats, &query(Some(1085), None, None, None, None))), vec![9]);
+        assert_eq!(sequences(select_stats(&stats, &query(None, Some(1005), None, None, None))), vec![0]);
+        assert!(select_stats(&stats, &query(Some(2000), None, None, None, None)).is_empty());
+        assert!(select_stats(&stats, &query(Some(1050), Some(1040), None, None, None)).is_empty());
+    }
+
+    #[test]
+    fn pages_through_the_selection() {
+        let stats = history();
+        assert_eq!(sequences(select_stats(&stats, &query(None, None, Some(3), None, None))), vec![0, 1, 2]);
+        assert_eq!(sequences(select_stats(&stats, &query(None, None, Some(3), Some(8), None))), vec![8, 9]);
+        assert_eq!(sequences(select_stats(&stats, &query(Some(1030), None, Some(2), Some(1), None))), vec![4, 5]);
+        assert!(select_stats(&stats, &query(None, None, None, Some(10), None)).is_empty());
+        assert!(select_stats(&stats, &query(None, None, Some(0), None, None)).is_empty());
+    }
+
+    #[test]
+    fn selects_the_latest_sample_of_the_range() {
+        let stats = history();
+        assert_eq!(sequences(select_stats(&stats, &query(None, None, None, None, Some(true)))), vec![9]);
+        assert_eq!(sequences(select_stats(&stats, &query(None, Some(1035), Some(5), Some(5), Some(true)))), vec![3]);
+        assert_eq!(sequences(select_stats(&stats, &query(None, None, None, None, Some(false)))).len(), 10);
+    }
+}
@@ -0,0 +1,165 @@
+// Network interface selection: the `iface` value of [cmdn_config] is a comma
+// separated list of interface names and globs ("eth0,wg0", "veth*"), where a
+// leading `!` leaves interfaces out ("!lo" is every interface but lo). The
+// legacy `total` value selects every interface. The link details sysinfo
+// does not report are read from /sys/class/net and getifaddrs(3).
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+use glob::Pattern;
+
+// ------------------------------------------------------------------
+
+pub struct InterfaceSelector {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl InterfaceSelector {
+    pub fn parse(iface: &str) -> Result<Self, String> {
+        let mut selector = InterfaceSelector { include: Vec::new(), exclude: Vec::new() };
+        if iface.trim() == "total" {
+            return Ok(selector);
+        }
+        for item in iface.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (patterns, glob) = match item.strip_prefix('!') {
+                Some(excluded) => (&mut selector.exclude, excluded.trim()),
+                None => (&mut selector.include, item),
+            };
+            patterns.push(Pattern::new(glob).map_err(|e| format!("invalid interface glob `{}`: {}", glob, e))?);
+        }
+        Ok(selector)
+    }
+
+    // Every interface is included when only exclusions are given
+    pub fn matches(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(name)))
+            && !self.exclude.iter().any(|p| p.matches(name))
+    }
+}
+
+// ------------------------------------------------------------------
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+// Link details sysinfo does not report, read from /sys/class/net/<iface>
+pub struct InterfaceLink {
+    pub operstate: Option<String>,
+    pub link_up: Option<bool>,
+    pub mtu: Option<u64>,
+    pub rx_dropped: Option<u64>,
+    pub tx_dropped: Option<u64>,
+}
+
+pub fn read_link(iface: &str) -> InterfaceLink {
+    let dir = Path::new(SYS_CLASS_NET).join(iface);
+    let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok().map(|value| value.trim().to_string());
+    let read_number = |file: &str| read(file).and_then(|value| value.parse::<u64>().ok());
+    // The carrier cannot be read while the interface is administratively down
+    let link_up = dir.exists().then(|| read("carrier").is_some_and(|carrier| carrier == "1"));
+    InterfaceLink {
+        operstate: read("operstate"),
+        link_up,
+        mtu: read_number("mtu"),
+        rx_dropped: read_number("statistics/rx_dropped"),
+        tx_dropped: read_number("statistics/tx_dropped"),
+    }
+}
+
+// IPv4 and IPv6 addresses of every interface, in CIDR notation
+pub fn ip_addresses() -> HashMap<String, Vec<String>> {
+    let mut addresses: HashMap<String, Vec<String>> = HashMap::new();
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: ifaddrs receives a list allocated by libc, freed below
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        eprintln!("[!] Could not list the IP addresses: {}", std::io::Error::last_os_error());
+        return addresses;
+    }
+
+    let mut current = ifaddrs;
+    while !current.is_null() {
+        // SAFETY: current points to an entry of the list returned by getifaddrs
+        let entry = unsafe { &*current };
+        current = entry.ifa_next;
+        if entry.ifa_addr.is_null() || entry.ifa_name.is_null() {
+            continue;
+        }
+        // SAFETY: ifa_name is a NUL terminated string and ifa_addr/ifa_netmask
+        // point to sockaddr structs of the family they announce
+        let (name, address) = unsafe {
+            let name = CStr::from_ptr(entry.ifa_name).to_string_lossy().to_string();
+            let address = match (*entry.ifa_addr).sa_family as i32 {
+                libc::AF_INET => {
+                    let addr = &*(entry.ifa_addr as *const libc::sockaddr_in);
+                    let prefix = (!entry.ifa_netmask.is_null())
+                        .then(|| (*(entry.ifa_netmask as *const libc::sockaddr_in)).sin_addr.s_addr.count_ones());
+                    Some((IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))), prefix))
+                }
+                libc::AF_INET6 => {
+                    let addr = &*(entry.ifa_addr as *const libc::sockaddr_in6);
+                    let prefix = (!entry.ifa_netmask.is_null()).then(|| {
+                        (*(entry.ifa_netmask as *const libc::sockaddr_in6)).sin6_addr.s6_addr.iter().map(|b| b.count_ones()).sum()
+                    });
+                    Some((IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)), prefix))
+                }
+                _ => None,
+            };
+            (name, address)
+        };
+        if let Some((ip, prefix)) = address {
+            let cidr = match prefix {
+                Some(prefix) => format!("{}/{}", ip, prefix),
+                None => ip.to_string(),
+            };
+            addresses.entry(name).or_default().push(cidr);
+        }
+    }
+    // SAFETY: ifaddrs was allocated by getifaddrs and is not used anymore
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    addresses
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(iface: &str) -> Vec<&'static str> {
+        let selector = InterfaceSelector::parse(iface).unwrap();
+        ["lo", "eth0", "eth1", "wlan0", "docker0", "veth12ab"]
+            .into_iter()
+            .filter(|name| selector.matches(name))
+            .collect()
+    }
+
+    #[test]
+    fn selects_every_interface_for_total() {
+        assert_eq!(selected("total"), vec!["lo", "eth0", "eth1", "wlan0", "docker0", "veth12ab"]);
+        assert_eq!(selected(" total "), selected("total"));
+    }
+
+    #[test]
+    fn selects_names_and_globs() {
+        assert_eq!(selected("eth0"), vec!["eth0"]);
+        assert_eq!(selected("eth*, wlan0"), vec!["eth0", "eth1", "wlan0"]);
+        assert_eq!(selected("eth[01],,"), vec!["eth0", "eth1"]);
+        assert!(selected("enp0s3").is_empty());
+    }
+
+    #[test]
+    fn leaves_out_the_exclusions() {
+        // Only exclusions: everything else is selected
+        assert_eq!(selected("!lo, ! docker*,!veth*"), vec!["eth0", "eth1", "wlan0"]);
+        assert_eq!(selected("*,!eth1"), vec!["lo", "eth0", "wlan0", "docker0", "veth12ab"]);
+        assert_eq!(selected("eth*,!eth0"), vec!["eth1"]);
+    }
+
+    #[test]
+    fn rejects_invalid_globs() {
+        assert!(InterfaceSelector::parse("eth[").is_err_and(|e| e.contains("invalid interface glob `eth[`")));
+        assert!(InterfaceSelector::parse("eth0,!wl[").is_err());
+    }
+}
@@ -0,0 +1,609 @@
+// Alert notifications: every notifier gets its own thread and queue, so that
+// a slow or unreachable backend neither delays the collection loop nor the
+// other notifiers. Failed deliveries are retried with an exponential backoff,
+// and a notifier never delivers twice in a row the same event for an alert.
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde_json::Value;
+
+use crate::alerts::{AlertEvent, AlertTransition};
+use crate::config::{EmailConfig, WebhookConfig};
+use crate::v2;
+
+// ------------------------------------------------------------------
+
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_BACKOFF_SECS: u64 = 2;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+// ------------------------------------------------------------------
+
+pub trait Notifier: Send {
+    fn describe(&self) -> String;
+    fn notify(&self, event: &AlertEvent) -> Result<(), String>;
+}
+
+// How hard a notifier tries before giving up on an event
+#[derive(Clone, Copy)]
+pub struct Delivery {
+    pub retries: u32,
+    pub backoff_secs: u64,
+}
+
+impl Delivery {
+    pub fn new(retries: Option<u32>, backoff_secs: Option<u64>) -> Self {
+        Delivery {
+            retries: retries.unwrap_or(DEFAULT_RETRIES),
+            backoff_secs: backoff_secs.unwrap_or(DEFAULT_BACKOFF_SECS),
+        }
+    }
+}
+
+// The queues of the running notifiers
+pub struct Notifiers {
+    queues: Vec<mpsc::Sender<AlertEvent>>,
+}
+
+impl Notifiers {
+    pub fn start(notifiers: Vec<(Box<dyn Notifier>, Delivery)>) -> Self {
+        let mut queues: Vec<mpsc::Sender<AlertEvent>> = Vec::new();
+        for (notifier, delivery) in notifiers {
+            let (sender, receiver) = mpsc::channel::<AlertEvent>();
+            thread::spawn(move || run_notifier(notifier, delivery, receiver));
+            queues.push(sender);
+        }
+        Notifiers { queues }
+    }
+
+    pub fn send(&self, event: &AlertEvent) {
+        for queue in &self.queues {
+            let _ = queue.send(event.clone());
+        }
+    }
+}
+
+fn run_notifier(notifier: Box<dyn Notifier>, delivery: Delivery, receiver: mpsc::Receiver<AlertEvent>) {
+    // Last event delivered for each alert
+    let mut delivered: HashMap<String, AlertTransition> = HashMap::new();
+
+    for event in receiver {
+        if delivered.get(&event.fingerprint) == Some(&event.event) {
+            continue;
+        }
+        let mut backoff_secs = delivery.backoff_secs;
+        for attempt in 0..=delivery.retries {
+            match notifier.notify(&event) {
+                Ok(()) => {
+                    delivered.insert(event.fingerprint.clone(), event.event);
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("[!] Could not notify `{}` to {} (attempt {}/{}): {}",
+                        event.fingerprint, notifier.describe(), attempt + 1, delivery.retries + 1, e);
+                    if attempt < delivery.retries {
+                        thread::sleep(Duration::from_secs(backoff_secs));
+                        backoff_secs *= 2;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------
+
+// POST the event to an URL, as JSON or as the configured body template
+pub struct WebhookNotifier {
+    agent: ureq::Agent,
+    url: String,
+    body: Option<String>,
+    content_type: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(webhook_config: &WebhookConfig) -> Self {
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS)))
+            .build()
+            .into();
+        WebhookNotifier {
+            agent,
+            url: webhook_config.url.clone(),
+            body: webhook_config.body.clone(),
+            content_type: webhook_config.content_type.clone().unwrap_or(String::from("application/json")),
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn describe(&self) -> String {
+        format!("webhook `{}`", self.url)
+    }
+
+    fn notify(&self, event: &AlertEvent) -> Result<(), String> {
+        let body = match &self.body {
+            Some(template) => render_template(template, event),
+            None => serde_json::to_string(event).map_err(|e| e.to_string())?,
+        };
+        self.agent
+            .post(&self.url)
+            .header("Content-Type", &self.content_type)
+            .send(body)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+// ------------------------------------------------------------------
+
+// Send the event by email through an SMTP relay
+pub struct EmailNotifier {
+    transport: SmtpTransport,
+    smtp_server: String,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailNotifier {
+    pub fn new(email_config: &EmailConfig) -> Result<Self, String> {
+        let server = email_config.smtp_server.as_str();
+        let mut builder = match email_config.security.as_deref().unwrap_or("starttls") {
+            "starttls" => SmtpTransport::starttls_relay(server).map_err(|e| e.to_string())?.port(587),
+            "tls" => SmtpTransport::relay(server).map_err(|e| e.to_string())?.port(465),
+            "plain" => SmtpTransport::builder_dangerous(server).port(25),
+            other => return Err(format!("unknown security `{}`, expected starttls, tls or plain", other)),
+        };
+        if let Some(port) = email_config.smtp_port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&email_config.username, &email_config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        let transport = builder
+            .timeout(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS)))
+            .build();
+
+        let from: Mailbox = email_config
+            .from
+            .parse()
+            .map_err(|e| format!("invalid sender `{}`: {}", email_config.from, e))?;
+        let mut to: Vec<Mailbox> = Vec::new();
+        for recipient in &email_config.to {
+            to.push(recipient.parse().map_err(|e| format!("invalid recipient `{}`: {}", recipient, e))?);
+        }
+        if to.is_empty() {
+            return Err(String::from("no recipient configured"));
+        }
+
+        Ok(EmailNotifier {
+            transport,
+            smtp_server: email_config.smtp_server.clone(),
+            from,
+            to,
+        })
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn describe(&self) -> String {
+        format!("email relay `{}`", self.smtp_server)
+    }
+
+    fn notify(&self, event: &AlertEvent) -> Result<(), String> {
+        let (label, detail) = match event.event {
+            AlertTransition::Firing => ("FIRING", format!("went beyond {}", event.alert.threshold)),
+            AlertTransition::Resolved => ("RESOLVED", format!("is back within {}", event.alert.clear)),
+        };
+        let subject = format!("[stats-exporter] {} {} on {}", label, event.alert.rule, event.hostname);
+        let body = format!(
+            "Alert `{}` on {}: {} {} (value {:.1}).\n\nCurrent usage statistics:\n{}",
+            event.alert.rule,
+            event.hostname,
+            event.alert.metric,
+            detail,
+            event.alert.value,
+            summarize_basic_stats(&event.basic_stats)
+        );
+
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for recipient in &self.to {
+            message = message.to(recipient.clone());
+        }
+        let message = message
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| e.to_string())?;
+        self.transport.send(&message).map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+// One line per host statistic, "n/a" for what was not collected
+fn summarize_basic_stats(basic_stats: &v2::BasicStats) -> String {
+    let percent = |value: Option<f64>| value.map_or(String::from("n/a"), |v| format!("{:.1}%", v));
+    let mib = |value: Option<u64>| value.map_or(String::from("n/a"), |v| format!("{} MiB", v / 1024 / 1024));
+    let kbps = |value: Option<f64>| value.map_or(String::from("n/a"), |v| format!("{:.1} Kbps", v));
+    let load = |value: Option<f64>| value.map_or(String::from("n/a"), |v| format!("{:.2}", v));
+    let lines: [(&str, String); 7] = [
+        ("CPU", percent(basic_stats.cpu_percent)),
+        ("RAM", format!("{} ({} of {})", percent(basic_stats.ram_percent), mib(basic_stats.ram_used_bytes), mib(basic_stats.ram_total_bytes))),
+        ("Root filesystem", percent(basic_stats.root_fs_percent)),
+        ("Swap", format!("{} ({} of {})", percent(basic_stats.swap_percent), mib(basic_stats.swap_used_bytes), mib(basic_stats.swap_total_bytes))),
+        ("Network", format!("{} down / {} up", kbps(basic_stats.net_down_kbps), kbps(basic_stats.net_up_kbps))),
+        ("Temperature", basic_stats.temperature_celsius.map_or(String::from("n/a"), |v| format!("{:.1} °C", v))),
+        ("Load average", format!("{} {} {}", load(basic_stats.load_average_1m), load(basic_stats.load_average_5m), load(basic_stats.load_average_15m))),
+    ];
+    let mut summary = String::new();
+    for (name, value) in lines {
+        summary.push_str(&format!("  .- {}: {}\n", name, value));
+    }
+    summary
+}
+
+// ------------------------------------------------------------------
+
+// Replace the {{placeholders}} of a template with the values of the event.
+// Text values are JSON escaped (without the quotes) so that they can be used
+// inside the strings of a JSON body.
+pub fn render_template(template: &str, event: &AlertEvent) -> String {
+    let text = |value: &str| {
+        let quoted = Value::String(value.to_string()).to_string();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let event_name = match event.event {
+        AlertTransition::Firing => "firing",
+        AlertTransition::Resolved => "resolved",
+    };
+    let placeholders: [(&str, String); 10] = [
+        ("event", event_name.to_string()),
+        ("fingerprint", text(&event.fingerprint)),
+        ("hostname", text(&event.hostname)),
+        ("timestamp", event.timestamp.to_string()),
+        ("rule", text(&event.alert.rule)),
+        ("metric", text(&event.alert.metric)),
+        ("value", format!("{:.1}", event.alert.value)),
+        ("threshold", event.alert.threshold.to_string()),
+        ("clear", event.alert.clear.to_string()),
+        ("since", event.alert.since.to_string()),
+    ];
+    let mut rendered = template.to_string();
+    for (name, value) in placeholders {
+        rendered = rendered.replace(&format!("{{{{{}}}}}", name), &value);
+    }
+    rendered
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use crate::alerts::{AlertState, AlertStatus};
+
+    fn event(transition: AlertTransition, rule: &str) -> AlertEvent {
+        AlertEvent {
+            event: transition,
+            fingerprint: format!("{}/basic_stats.ram_percent", rule),
+            hostname: String::from("vm"),
+            timestamp: 1700000000,
+            alert: AlertStatus {
+                rule: rule.to_string(),
+                metric: String::from("basic_stats.ram_percent"),
+                state: match transition {
+                    AlertTransition::Firing => AlertState::Firing,
+                    AlertTransition::Resolved => AlertState::Ok,
+                },
+                value: 93.25,
+                threshold: 90.,
+                clear: 85.5,
+                since: 1699999940,
+                last_evaluated: 1700000000,
+            },
+            basic_stats: v2::BasicStats::default(),
+        }
+    }
+
+    // Records every attempt, failing the first `failures` of them
+    struct Recorder {
+        attempts: Arc<Mutex<Vec<(String, AlertTransition, Instant)>>>,
+        failures: usize,
+    }
+
+    impl Notifier for Recorder {
+        fn describe(&self) -> String {
+            String::from("recorder")
+        }
+
+        fn notify(&self, event: &AlertEvent) -> Result<(), String> {
+            let mut attempts = self.attempts.lock().unwrap();
+            attempts.push((event.fingerprint.clone(), event.event, Instant::now()));
+            if attempts.len() <= self.failures {
+                return Err(String::from("unavailable"));
+            }
+            Ok(())
+        }
+    }
+
+    // Feed the events to a notifier and wait for it to go through them
+    fn deliver(failures: usize, delivery: Delivery, events: Vec<AlertEvent>) -> Vec<(String, AlertTransition, Instant)> {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let notifier = Recorder { attempts: Arc::clone(&attempts), failures };
+        let (sender, receiver) = mpsc::channel::<AlertEvent>();
+        for event in events {
+            sender.send(event).unwrap();
+        }
+        drop(sender);
+        run_notifier(Box::new(notifier), delivery, receiver);
+        let attempts = attempts.lock().unwrap().clone();
+        attempts
+    }
+
+    #[test]
+    fn delivers_each_transition_once() {
+        let attempts = deliver(0, Delivery::new(Some(0), Some(0)), vec![
+            event(AlertTransition::Firing, "ram"),
+            event(AlertTransition::Firing, "ram"),
+            event(AlertTransition::Firing, "cpu"),
+            event(AlertTransition::Resolved, "ram"),
+            event(AlertTransition::Resolved, "ram"),
+            event(AlertTransition::Firing, "ram"),
+        ]);
+        let delivered: Vec<(&str, AlertTransition)> = attempts.iter().map(|(f, e, _)| (f.as_str(), *e)).collect();
+        assert!(delivered == vec![
+            ("ram/basic_stats.ram_percent", AlertTransition::Firing),
+            ("cpu/basic_stats.ram_percent", AlertTransition::Firing),
+            ("ram/basic_stats.ram_percent", AlertTransition::Resolved),
+            ("ram/basic_stats.ram_percent", AlertTransition::Firing),
+        ]);
+    }
+
+    #[test]
+    fn retries_with_a_doubling_backoff() {
+        let attempts = deliver(2, Delivery::new(Some(2), Some(1)), vec![
+            event(AlertTransition::Firing, "ram"),
+            event(AlertTransition::Firing, "ram"),
+        ]);
+        // Two failures, then the delivery: the duplicate is not sent again
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts[1].2.duration_since(attempts[0].2) >= Duration::from_secs(1));
+        assert!(attempts[2].2.duration_since(attempts[1].2) >= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn gives_up_after_the_retries() {
+        let attempts = deliver(usize::MAX, Delivery::new(Some(1), Some(0)), vec![
+            event(AlertTransition::Firing, "ram"),
+            event(AlertTransition::Firing, "ram"),
+        ]);
+        // An event that could not be delivered is tried again when it comes back
+        assert_eq!(attempts.len(), 4);
+    }
+
+    struct Request {
+        request_line: String,
+        headers: Vec<String>,
+        body: String,
+        received_at: Instant,
+    }
+
+    // A stand-in webhook receiver answering 503 to the first `failures` requests
+    // and 200 afterwards
+    fn webhook_receiver(failures: usize) -> (String, mpsc::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers: Vec<String> = Vec::new();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    headers.push(header.trim().to_string());
+                }
+                let length = headers
+                    .iter()
+                    .find_map(|h| h.to_ascii_lowercase().strip_prefix("content-length:").map(|l| l.trim().parse::<usize>().unwrap()))
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                std::io::Read::read_exact(&mut reader, &mut body).unwrap();
+                let _ = sender.send(Request {
+                    request_line: request_line.trim().to_string(),
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                    received_at: Instant::now(),
+                });
+                let status = if index < failures { "503 Service Unavailable" } else { "200 OK" };
+                let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            }
+        });
+        (url, receiver)
+    }
+
+    #[test]
+    fn posts_the_rendered_body_to_the_webhook() {
+        let (url, requests) = webhook_receiver(2);
+        let webhook_config = WebhookConfig {
+            url: format!("{}/hooks/alerts", url),
+            body: Some(String::from(r#"{"text": "{{rule}} {{event}} on {{hostname}}"}"#)),
+            content_type: None,
+            retries: Some(2),
+            backoff_secs: Some(1),
+        };
+        let delivery = Delivery::new(webhook_config.retries, webhook_config.backoff_secs);
+        let (sender, receiver) = mpsc::channel::<AlertEvent>();
+        sender.send(event(AlertTransition::Firing, "ram")).unwrap();
+        drop(sender);
+        run_notifier(Box::new(WebhookNotifier::new(&webhook_config)), delivery, receiver);
+
+        // Two 503 answers, then the delivery
+        let received: Vec<Request> = requests.try_iter().collect();
+        assert_eq!(received.len(), 3);
+        for request in &received {
+            assert_eq!(request.request_line, "POST /hooks/alerts HTTP/1.1");
+            assert!(request.headers.iter().any(|h| h.eq_ignore_ascii_case("content-type: application/json")));
+            assert_eq!(request.body, r#"{"text": "ram firing on vm"}"#);
+        }
+        assert!(received[1].received_at.duration_since(received[0].received_at) >= Duration::from_secs(1));
+        assert!(received[2].received_at.duration_since(received[1].received_at) >= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn renders_the_template_placeholders() {
+        let rendered = render_template(
+            r#"{"text": "{{rule}} {{event}} on {{hostname}}: {{metric}} is {{value}} ({{threshold}}/{{clear}} since {{since}}) {{unknown}}"}"#,
+            &event(AlertTransition::Firing, "ram"),
+        );
+        assert_eq!(
+            rendered,
+            r#"{"text": "ram firing on vm: basic_stats.ram_percent is 93.2 (90/85.5 since 1699999940) {{unknown}}"}"#
+        );
+        let rendered = render_template("{{timestamp}} {{fingerprint}} {{event}}", &event(AlertTransition::Resolved, "ram"));
+        assert_eq!(rendered, "1700000000 ram/basic_stats.ram_percent resolved");
+    }
+
+    #[test]
+    fn escapes_the_text_values() {
+        let mut quoted = event(AlertTransition::Firing, "say \"hi\"\\\n");
+        quoted.hostname = String::from("tab\there");
+        let rendered = render_template(r#"{"rule": "{{rule}}", "host": "{{hostname}}"}"#, &quoted);
+        assert_eq!(rendered, r#"{"rule": "say \"hi\"\\\n", "host": "tab\there"}"#);
+        let parsed: Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(parsed["rule"], "say \"hi\"\\\n");
+        assert_eq!(parsed["host"], "tab\there");
+    }
+
+    #[test]
+    fn summarizes_the_basic_stats() {
+        let basic_stats = v2::BasicStats {
+            cpu_percent: Some(12.345),
+            ram_percent: Some(50.),
+            ram_used_bytes: Some(2 * 1024 * 1024 * 1024),
+            ram_total_bytes: Some(4 * 1024 * 1024 * 1024),
+            net_down_kbps: Some(1234.56),
+            net_up_kbps: Some(0.),
+            temperature_celsius: Some(48.),
+            load_average_1m: Some(0.5),
+            load_average_5m: Some(0.25),
+            load_average_15m: Some(0.125),
+            ..Default::default()
+        };
+        assert_eq!(
+            summarize_basic_stats(&basic_stats),
+            concat!(
+                "  .- CPU: 12.3%\n",
+                "  .- RAM: 50.0% (2048 MiB of 4096 MiB)\n",
+                "  .- Root filesystem: n/a\n",
+                "  .- Swap: n/a (n/a of n/a)\n",
+                "  .- Network: 1234.6 Kbps down / 0.0 Kbps up\n",
+                "  .- Temperature: 48.0 °C\n",
+                "  .- Load average: 0.50 0.25 0.12\n",
+            )
+        );
+    }
+
+    // A stand-in SMTP relay that accepts a single message and hands it over
+    fn smtp_sink() -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut stream = stream;
+            let mut transcript = String::new();
+            let mut in_data = false;
+            stream.write_all(b"220 sink ESMTP\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                transcript.push_str(&line);
+                let reply: &[u8] = if in_data {
+                    if line != ".\r\n" {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else {
+                    match line.get(..4).unwrap_or("").to_ascii_uppercase().as_str() {
+                        "EHLO" => b"250-sink\r\n250 8BITMIME\r\n",
+                        "DATA" => {
+                            in_data = true;
+                            b"354 go ahead\r\n"
+                        }
+                        "QUIT" => {
+                            stream.write_all(b"221 bye\r\n").unwrap();
+                            break;
+                        }
+                        _ => b"250 ok\r\n",
+                    }
+                };
+                stream.write_all(reply).unwrap();
+            }
+            let _ = sender.send(transcript);
+        });
+        (port, receiver)
+    }
+
+    #[test]
+    fn sends_the_alert_by_email() {
+        let (port, transcript) = smtp_sink();
+        let email_config = EmailConfig {
+            smtp_server: String::from("127.0.0.1"),
+            smtp_port: Some(port),
+            security: Some(String::from("plain")),
+            username: None,
+            password: None,
+            from: String::from("stats-exporter <exporter@example.com>"),
+            to: vec![String::from("ops@example.com"), String::from("oncall@example.com")],
+            retries: None,
+            backoff_secs: None,
+        };
+        let notifier = EmailNotifier::new(&email_config).unwrap();
+        let mut firing = event(AlertTransition::Firing, "ram");
+        firing.basic_stats.cpu_percent = Some(99.);
+        notifier.notify(&firing).unwrap();
+
+        let transcript = transcript.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(transcript.contains("MAIL FROM:<exporter@example.com>"));
+        assert!(transcript.contains("RCPT TO:<ops@example.com>"));
+        assert!(transcript.contains("RCPT TO:<oncall@example.com>"));
+        assert!(transcript.contains("Subject: [stats-exporter] FIRING ram on vm"));
+        assert!(transcript.contains("Alert `ram` on vm: basic_stats.ram_percent went beyond 90 (value 93.2)."));
+        assert!(transcript.contains("  .- CPU: 99.0%"));
+    }
+
+    #[test]
+    fn rejects_an_unknown_security() {
+        let email_config = EmailConfig {
+            smtp_server: String::from("127.0.0.1"),
+            smtp_port: None,
+            security: Some(String::from("ssl")),
+            username: None,
+            password: None,
+            from: String::from("exporter@example.com"),
+            to: vec![String::from("ops@example.com")],
+            retries: None,
+            backoff_secs: None,
+        };
+        assert!(EmailNotifier::new(&email_config).is_err_and(|e| e.contains("unknown security `ssl`")));
+    }
+}
@@ -0,0 +1,195 @@
+// Process collector: reports the processes that use the most CPU and memory
+// along with the state of the watched ones, on the polling interval of the
+// [processes_config] section. The process table it reads is shared with the
+// process counts of the basic stats, so that /proc is walked once per sample.
+use std::path::Path;
+use std::time::Instant;
+
+use regex::Regex;
+use sysinfo::{Process, ProcessRefreshKind, ProcessStatus, System, ThreadKind, UpdateKind, Users};
+
+use crate::config::ProcessesConfig;
+use crate::v2::{BasicStats, ProcessStats, ProcessesStats, WatchedProcessStats};
+
+// ------------------------------------------------------------------
+
+// A watched process and the pids its instances had at the previous poll
+struct Watch {
+    name: String,
+    cmdline: Option<Regex>,
+    pids: Vec<u32>,
+    seen: bool,
+    restarts: u64,
+}
+
+impl Watch {
+    fn matches(&self, process: &Process) -> bool {
+        match &self.cmdline {
+            Some(cmdline) => cmdline.is_match(&process.cmd().join(" ")),
+            // The process name is truncated to 15 characters, the executable one is not
+            None => {
+                process.name() == self.name
+                    || process.exe().and_then(Path::file_name).is_some_and(|exe| exe == self.name.as_str())
+            }
+        }
+    }
+
+    // Count a restart when none of the previous instances is left but new ones
+    // are running, or when the process comes back after being gone
+    fn update_pids(&mut self, pids: Vec<u32>) {
+        if !pids.is_empty() && self.seen && !self.pids.iter().any(|pid| pids.contains(pid)) {
+            self.restarts += 1;
+        }
+        self.seen |= !pids.is_empty();
+        self.pids = pids;
+    }
+}
+
+pub struct ProcessesCollector {
+    top: usize,
+    watches: Vec<Watch>,
+    polling_secs: usize,
+}
+
+impl ProcessesCollector {
+    // Watched processes with an invalid regex are reported and left out
+    pub fn new(processes_config: &ProcessesConfig) -> Self {
+        let mut watches: Vec<Watch> = Vec::new();
+        for watched in &processes_config.watch {
+            let cmdline = match watched.cmdline.as_deref().map(Regex::new).transpose() {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("[!] Watched process `{}` ignored: {}", watched.name, e);
+                    continue;
+                }
+            };
+            watches.push(Watch { name: watched.name.clone(), cmdline, pids: Vec::new(), seen: false, restarts: 0 });
+        }
+        ProcessesCollector {
+            top: processes_config.top,
+            watches,
+            polling_secs: processes_config.polling_secs,
+        }
+    }
+
+    pub fn polling_secs(&self) -> usize {
+        self.polling_secs
+    }
+
+    pub fn collect(&mut self, table: &ProcessTable) -> ProcessesStats {
+        let mut processes: Vec<&Process> = table.processes().collect();
+        processes.sort_by(|a, b| b.cpu_usage().total_cmp(&a.cpu_usage()));
+        let top_cpu = processes.iter().take(self.top).map(|p| table.process_stats(p)).collect();
+        processes.sort_by_key(|p| std::cmp::Reverse(p.memory()));
+        let top_memory = processes.iter().take(self.top).map(|p| table.process_stats(p)).collect();
+
+        let total_memory = table.system.total_memory();
+        let mut watched: Vec<WatchedProcessStats> = Vec::new();
+        for watch in self.watches.iter_mut() {
+            let instances: Vec<&&Process> = processes.iter().filter(|p| watch.matches(p)).collect();
+            let rss_bytes: u64 = instances.iter().map(|p| p.memory()).sum();
+            watch.update_pids(instances.iter().map(|p| p.pid().as_u32()).collect());
+            watched.push(WatchedProcessStats {
+                name: watch.name.clone(),
+                running: !instances.is_empty(),
+                count: instances.len() as u64,
+                cpu_percent: instances.iter().fold(0.0, |total, p| total + p.cpu_usage() as f64),
+                rss_bytes,
+                memory_percent: (total_memory > 0).then(|| rss_bytes as f64 / total_memory as f64 * 100.),
+                restarts: watch.restarts,
+            });
+        }
+
+        ProcessesStats { top_cpu, top_memory, watched }
+    }
+}
+
+// ------------------------------------------------------------------
+
+// The process list with their states, plus the CPU, memory, disk usage, user
+// and command line of each process when the collector needs them
+pub struct ProcessTable {
+    system: System,
+    users: Users,
+    refresh_kind: ProcessRefreshKind,
+    last_refresh: Option<Instant>,
+    elapsed: Option<f64>,
+}
+
+impl ProcessTable {
+    pub fn new(details: bool) -> Self {
+        // CPU usage and disk rates are computed against the previous refresh,
+        // so every refresh has to load them
+        let refresh_kind = if details {
+            ProcessRefreshKind::new()
+                .with_cpu()
+                .with_memory()
+                .with_disk_usage()
+                .with_user(UpdateKind::OnlyIfNotSet)
+                .with_cmd(UpdateKind::OnlyIfNotSet)
+                .with_exe(UpdateKind::OnlyIfNotSet)
+        } else {
+            ProcessRefreshKind::new()
+        };
+        ProcessTable { system: System::new(), users: Users::new(), refresh_kind, last_refresh: None, elapsed: None }
+    }
+
+    pub fn refresh(&mut self) {
+        self.system.refresh_processes_specifics(self.refresh_kind);
+        if self.refresh_kind.cpu() {
+            self.system.refresh_memory();
+            self.users.refresh_list();
+        }
+        self.elapsed = self.last_refresh.map(|last| last.elapsed().as_secs_f64());
+        self.last_refresh = Some(Instant::now());
+    }
+
+    // Threads of a process are listed as processes of their own, leave them out
+    fn processes(&self) -> impl Iterator<Item = &Process> {
+        self.system
+            .processes()
+            .values()
+            .filter(|process| process.thread_kind() != Some(ThreadKind::Userland))
+    }
+
+    // Fill the process counts of the basic stats
+    pub fn count(&self, basic_stats: &mut BasicStats) {
+        let (mut total, mut running, mut sleeping, mut zombie) = (0, 0, 0, 0);
+        for process in self.processes() {
+            total += 1;
+            match process.status() {
+                ProcessStatus::Run => running += 1,
+                ProcessStatus::Sleep | ProcessStatus::UninterruptibleDiskSleep | ProcessStatus::Idle => sleeping += 1,
+                ProcessStatus::Zombie => zombie += 1,
+                _ => {}
+            }
+        }
+        basic_stats.processes_total = Some(total);
+        basic_stats.processes_running = Some(running);
+        basic_stats.processes_sleeping = Some(sleeping);
+        basic_stats.processes_zombie = Some(zombie);
+    }
+
+    fn process_stats(&self, process: &Process) -> ProcessStats {
+        let disk_usage = process.disk_usage();
+        // A process started since the previous refresh has no previous counters
+        let elapsed = self.elapsed.filter(|secs| *secs > 0.0 && process.run_time() as f64 >= *secs);
+        let total_memory = self.system.total_memory();
+        ProcessStats {
+            pid: process.pid().as_u32(),
+            name: process.name().to_string(),
+            cmdline: process.cmd().join(" "),
+            user: process
+                .user_id()
+                .and_then(|uid| self.users.get_user_by_id(uid))
+                .map(|user| user.name().to_string()),
+            cpu_percent: process.cpu_usage() as f64,
+            rss_bytes: process.memory(),
+            memory_percent: (total_memory > 0).then(|| process.memory() as f64 / total_memory as f64 * 100.),
+            disk_read_bytes_per_sec: elapsed.map(|secs| disk_usage.read_bytes as f64 / secs),
+            disk_write_bytes_per_sec: elapsed.map(|secs| disk_usage.written_bytes as f64 / secs),
+            disk_read_bytes_total: disk_usage.total_read_bytes,
+            disk_written_bytes_total: disk_usage.total_written_bytes,
+        }
+    }
+}
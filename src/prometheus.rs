@@ -0,0 +1,548 @@
+// Render the collected statistics in the Prometheus text exposition format
+// (https://prometheus.io/docs/instrumenting/exposition_formats/).
+use std::fmt::Write;
+
+use crate::v2::{BasicStats, Stats};
+
+// ------------------------------------------------------------------
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const PREFIX: &str = "stats_exporter";
+
+// ------------------------------------------------------------------
+
+// Gauges go up and down, counters only grow (and reset when the source restarts)
+#[derive(Clone, Copy)]
+enum MetricType {
+    Gauge,
+    Counter,
+}
+
+// A metric family: one HELP/TYPE header followed by all of its samples
+struct MetricFamily {
+    name: String,
+    help: &'static str,
+    metric_type: MetricType,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl MetricFamily {
+    fn new(name: &str, help: &'static str, metric_type: MetricType) -> Self {
+        MetricFamily {
+            name: format!("{}_{}", PREFIX, name),
+            help,
+            metric_type,
+            samples: Vec::new(),
+        }
+    }
+
+    // Add a sample, skipping values that were not collected
+    fn push(&mut self, labels: Vec<(&'static str, String)>, value: Option<f64>) {
+        if let Some(v) = value {
+            self.samples.push((labels, v));
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        if self.samples.is_empty() {
+            return;
+        }
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let metric_type = match self.metric_type {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+        };
+        let _ = writeln!(out, "# TYPE {} {}", self.name, metric_type);
+        for (labels, value) in &self.samples {
+            let _ = writeln!(out, "{}{} {}", self.name, render_labels(labels), value);
+        }
+    }
+}
+
+// Escape a label value as required by the exposition format
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render_labels(labels: &[(&'static str, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+// ------------------------------------------------------------------
+
+// The set of gauges derived from a BasicStats block, shared by the host
+// itself and by every kubernetes node
+struct BasicFamilies {
+    cpu: MetricFamily,
+    cpu_core: MetricFamily,
+    cpu_mode: MetricFamily,
+    ram: MetricFamily,
+    root_fs: MetricFamily,
+    root_fs_inodes: MetricFamily,
+    swap_fs: MetricFamily,
+    net_down: MetricFamily,
+    net_up: MetricFamily,
+    temperature: MetricFamily,
+    load_average: MetricFamily,
+    uptime: MetricFamily,
+    boot_time: MetricFamily,
+    processes: MetricFamily,
+}
+
+impl BasicFamilies {
+    fn new(prefix: &str) -> Self {
+        BasicFamilies {
+            cpu: MetricFamily::new(&format!("{}cpu_usage_percent", prefix), "Average CPU usage across all cores in percent.", MetricType::Gauge),
+            cpu_core: MetricFamily::new(&format!("{}cpu_core_usage_percent", prefix), "CPU usage of a single core in percent.", MetricType::Gauge),
+            cpu_mode: MetricFamily::new(&format!("{}cpu_mode_percent", prefix), "Share of the CPU time spent in each mode since the previous sample.", MetricType::Gauge),
+            ram: MetricFamily::new(&format!("{}memory_usage_percent", prefix), "Used memory in percent of the total memory.", MetricType::Gauge),
+            root_fs: MetricFamily::new(&format!("{}root_fs_usage_percent", prefix), "Used space of the root filesystem in percent.", MetricType::Gauge),
+            root_fs_inodes: MetricFamily::new(&format!("{}root_fs_inodes_usage_percent", prefix), "Used inodes of the root filesystem in percent.", MetricType::Gauge),
+            swap_fs: MetricFamily::new(&format!("{}swap_usage_percent", prefix), "Used swap in percent of the total swap.", MetricType::Gauge),
+            net_down: MetricFamily::new(&format!("{}network_receive_kbps", prefix), "Network download rate in kilobits per second.", MetricType::Gauge),
+            net_up: MetricFamily::new(&format!("{}network_transmit_kbps", prefix), "Network upload rate in kilobits per second.", MetricType::Gauge),
+            temperature: MetricFamily::new(&format!("{}temperature_celsius", prefix), "Temperature of the configured sensor in degrees Celsius.", MetricType::Gauge),
+            load_average: MetricFamily::new(&format!("{}load_average", prefix), "System load average over the period.", MetricType::Gauge),
+            uptime: MetricFamily::new(&format!("{}uptime_seconds", prefix), "Time elapsed since the system booted.", MetricType::Gauge),
+            boot_time: MetricFamily::new(&format!("{}boot_time_seconds", prefix), "Unix time at which the system booted.", MetricType::Gauge),
+            processes: MetricFamily::new(&format!("{}processes", prefix), "Number of processes in each state.", MetricType::Gauge),
+        }
+    }
+
+    // `ifaces` are the interfaces the network rates add up, `temp_item` the sensor
+    fn push(&mut self, basic_stats: &BasicStats, labels: &[(&'static str, String)], ifaces: &str, temp_item: &str) {
+        self.cpu.push(labels.to_vec(), basic_stats.cpu_percent);
+        for (core, percent) in basic_stats.cpu_cores_percent.iter().flatten().enumerate() {
+            let mut core_labels = labels.to_vec();
+            core_labels.push(("core", core.to_string()));
+            self.cpu_core.push(core_labels, Some(*percent));
+        }
+        let modes: [(&str, Option<f64>); 8] = [
+            ("user", basic_stats.cpu_user_percent),
+            ("nice", basic_stats.cpu_nice_percent),
+            ("system", basic_stats.cpu_system_percent),
+            ("idle", basic_stats.cpu_idle_percent),
+            ("iowait", basic_stats.cpu_iowait_percent),
+            ("irq", basic_stats.cpu_irq_percent),
+            ("softirq", basic_stats.cpu_softirq_percent),
+            ("steal", basic_stats.cpu_steal_percent),
+        ];
+        for (mode, percent) in modes {
+            let mut mode_labels = labels.to_vec();
+            mode_labels.push(("mode", mode.to_string()));
+            self.cpu_mode.push(mode_labels, percent);
+        }
+        self.ram.push(labels.to_vec(), basic_stats.ram_percent);
+        self.root_fs.push(labels.to_vec(), basic_stats.root_fs_percent);
+        self.root_fs_inodes.push(labels.to_vec(), basic_stats.root_fs_inodes_percent);
+        self.swap_fs.push(labels.to_vec(), basic_stats.swap_percent);
+
+        let mut net_labels = labels.to_vec();
+        if !ifaces.is_empty() {
+            net_labels.push(("iface", ifaces.to_string()));
+        }
+        self.net_down.push(net_labels.clone(), basic_stats.net_down_kbps);
+        self.net_up.push(net_labels, basic_stats.net_up_kbps);
+
+        if !temp_item.is_empty() {
+            let mut temp_labels = labels.to_vec();
+            temp_labels.push(("sensor", temp_item.to_string()));
+            self.temperature.push(temp_labels, basic_stats.temperature_celsius);
+        }
+
+        let periods: [(&str, Option<f64>); 3] = [
+            ("1m", basic_stats.load_average_1m),
+            ("5m", basic_stats.load_average_5m),
+            ("15m", basic_stats.load_average_15m),
+        ];
+        for (period, load) in periods {
+            let mut period_labels = labels.to_vec();
+            period_labels.push(("period", period.to_string()));
+            self.load_average.push(period_labels, load);
+        }
+        self.uptime.push(labels.to_vec(), basic_stats.uptime_secs.map(|secs| secs as f64));
+        self.boot_time.push(labels.to_vec(), basic_stats.boot_time.map(|time| time as f64));
+        let states: [(&str, Option<u64>); 3] = [
+            ("running", basic_stats.processes_running),
+            ("sleeping", basic_stats.processes_sleeping),
+            ("zombie", basic_stats.processes_zombie),
+        ];
+        for (state, count) in states {
+            let mut state_labels = labels.to_vec();
+            state_labels.push(("state", state.to_string()));
+            self.processes.push(state_labels, count.map(|count| count as f64));
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        self.cpu.render(out);
+        self.cpu_core.render(out);
+        self.cpu_mode.render(out);
+        self.ram.render(out);
+        self.root_fs.render(out);
+        self.root_fs_inodes.render(out);
+        self.swap_fs.render(out);
+        self.net_down.render(out);
+        self.net_up.render(out);
+        self.temperature.render(out);
+        self.load_average.render(out);
+        self.uptime.render(out);
+        self.boot_time.render(out);
+        self.processes.render(out);
+    }
+}
+
+// ------------------------------------------------------------------
+
+// Render the latest sample. `temp_item` is the configured temperature sensor,
+// used as a label value; the host network rates are labelled with the
+// interfaces they add up (e.g. `eth0,wlan0`), not with the configured globs.
+pub fn render_metrics(stats: Option<&Stats>, temp_item: &str) -> String {
+    let mut out = String::new();
+
+    let mut build_info = MetricFamily::new("build_info", "Version of the stats-exporter serving these metrics.", MetricType::Gauge);
+    build_info.push(vec![("version", crate::VERSION.to_string())], Some(1.));
+    build_info.render(&mut out);
+
+    let stats = match stats {
+        Some(s) => s,
+        None => return out,
+    };
+
+    let mut sample_timestamp = MetricFamily::new("sample_timestamp_seconds", "Unix time at which the latest sample was collected.", MetricType::Gauge);
+    sample_timestamp.push(vec![("hostname", stats.hostname.clone())], Some(stats.timestamp as f64));
+    sample_timestamp.render(&mut out);
+
+    let mut sample_sequence = MetricFamily::new("sample_sequence", "Sequence number of the latest sample.", MetricType::Gauge);
+    sample_sequence.push(vec![("hostname", stats.hostname.clone())], Some(stats.sequence as f64));
+    sample_sequence.render(&mut out);
+
+    let mut collection_duration = MetricFamily::new("collection_duration_seconds", "Time taken to collect the latest sample.", MetricType::Gauge);
+    collection_duration.push(vec![("hostname", stats.hostname.clone())], Some(stats.collection_secs));
+    collection_duration.render(&mut out);
+
+    let mut basic = BasicFamilies::new("");
+    let ifaces: Vec<&str> = stats.network_stats.iter().map(|iface| iface.iface.as_str()).collect();
+    basic.push(&stats.basic_stats, &[], &ifaces.join(","), temp_item);
+    basic.render(&mut out);
+
+    let mut iface_down = MetricFamily::new("network_interface_receive_kbps", "Download rate of a selected network interface in kilobits per second.", MetricType::Gauge);
+    let mut iface_up = MetricFamily::new("network_interface_transmit_kbps", "Upload rate of a selected network interface in kilobits per second.", MetricType::Gauge);
+    let mut iface_rx_packets = MetricFamily::new("network_interface_receive_packets_per_second", "Packets received per second by a selected network interface.", MetricType::Gauge);
+    let mut iface_tx_packets = MetricFamily::new("network_interface_transmit_packets_per_second", "Packets sent per second by a selected network interface.", MetricType::Gauge);
+    let mut iface_rx_bytes = MetricFamily::new("network_interface_receive_bytes_total", "Bytes received by a selected network interface since boot.", MetricType::Counter);
+    let mut iface_tx_bytes = MetricFamily::new("network_interface_transmit_bytes_total", "Bytes sent by a selected network interface since boot.", MetricType::Counter);
+    let mut iface_rx_errors = MetricFamily::new("network_interface_receive_errors_total", "Receive errors of a selected network interface since boot.", MetricType::Counter);
+    let mut iface_tx_errors = MetricFamily::new("network_interface_transmit_errors_total", "Transmit errors of a selected network interface since boot.", MetricType::Counter);
+    let mut iface_rx_dropped = MetricFamily::new("network_interface_receive_dropped_total", "Received packets dropped by a selected network interface since boot.", MetricType::Counter);
+    let mut iface_tx_dropped = MetricFamily::new("network_interface_transmit_dropped_total", "Packets to send dropped by a selected network interface since boot.", MetricType::Counter);
+    let mut iface_link_up = MetricFamily::new("network_interface_link_up", "Whether a selected network interface has a carrier (1) or not (0).", MetricType::Gauge);
+    let mut iface_mtu = MetricFamily::new("network_interface_mtu_bytes", "MTU of a selected network interface.", MetricType::Gauge);
+    let mut iface_info = MetricFamily::new("network_interface_info", "MAC address and operational state of a selected network interface, always 1.", MetricType::Gauge);
+    let mut iface_address = MetricFamily::new("network_interface_address_info", "IP address of a selected network interface in CIDR notation, always 1.", MetricType::Gauge);
+    for iface in &stats.network_stats {
+        let labels = vec![("iface", iface.iface.clone())];
+        iface_down.push(labels.clone(), Some(iface.down_kbps));
+        iface_up.push(labels.clone(), Some(iface.up_kbps));
+        iface_rx_packets.push(labels.clone(), Some(iface.rx_packets_per_sec));
+        iface_tx_packets.push(labels.clone(), Some(iface.tx_packets_per_sec));
+        iface_rx_bytes.push(labels.clone(), Some(iface.rx_bytes_total as f64));
+        iface_tx_bytes.push(labels.clone(), Some(iface.tx_bytes_total as f64));
+        iface_rx_errors.push(labels.clone(), Some(iface.rx_errors_total as f64));
+        iface_tx_errors.push(labels.clone(), Some(iface.tx_errors_total as f64));
+        iface_rx_dropped.push(labels.clone(), iface.rx_dropped_total.map(|dropped| dropped as f64));
+        iface_tx_dropped.push(labels.clone(), iface.tx_dropped_total.map(|dropped| dropped as f64));
+        iface_link_up.push(labels.clone(), iface.link_up.map(|up| if up { 1. } else { 0. }));
+        iface_mtu.push(labels.clone(), iface.mtu.map(|mtu| mtu as f64));
+        let mut info_labels = labels.clone();
+        info_labels.push(("mac_address", iface.mac_address.clone().unwrap_or_default()));
+        info_labels.push(("operstate", iface.operstate.clone().unwrap_or_default()));
+        iface_info.push(info_labels, Some(1.));
+        for address in &iface.ip_addresses {
+            let mut address_labels = labels.clone();
+            address_labels.push(("address", address.clone()));
+            iface_address.push(address_labels, Some(1.));
+        }
+    }
+    iface_down.render(&mut out);
+    iface_up.render(&mut out);
+    iface_rx_packets.render(&mut out);
+    iface_tx_packets.render(&mut out);
+    iface_rx_bytes.render(&mut out);
+    iface_tx_bytes.render(&mut out);
+    iface_rx_errors.render(&mut out);
+    iface_tx_errors.render(&mut out);
+    iface_rx_dropped.render(&mut out);
+    iface_tx_dropped.render(&mut out);
+    iface_link_up.render(&mut out);
+    iface_mtu.render(&mut out);
+    iface_info.render(&mut out);
+    iface_address.render(&mut out);
+
+    let mut fs_used = MetricFamily::new("filesystem_usage_percent", "Used space of a configured filesystem in percent.", MetricType::Gauge);
+    for fs in &stats.file_systems_stats {
+        fs_used.push(
+            vec![("fs_name", fs.fs_name.clone()), ("fs_mount_point", fs.fs_mount_point.clone())],
+            fs.fs_used_percent,
+        );
+    }
+    fs_used.render(&mut out);
+
+    let mut fs_mounted = MetricFamily::new("filesystem_mounted", "Whether a configured filesystem is mounted (1) or missing (0).", MetricType::Gauge);
+    for fs in &stats.file_systems_stats {
+        fs_mounted.push(
+            vec![("fs_name", fs.fs_name.clone()), ("fs_mount_point", fs.fs_mount_point.clone())],
+            fs.fs_mounted.map(|mounted| if mounted { 1. } else { 0. }),
+        );
+    }
+    fs_mounted.render(&mut out);
+
+    let mut fs_size = MetricFamily::new("filesystem_size_bytes", "Total size of a configured filesystem in bytes.", MetricType::Gauge);
+    let mut fs_used_bytes = MetricFamily::new("filesystem_used_bytes", "Used space of a configured filesystem in bytes.", MetricType::Gauge);
+    let mut fs_available = MetricFamily::new("filesystem_available_bytes", "Space of a configured filesystem still available in bytes.", MetricType::Gauge);
+    let mut fs_inodes = MetricFamily::new("filesystem_inodes", "Total number of inodes of a configured filesystem.", MetricType::Gauge);
+    let mut fs_inodes_free = MetricFamily::new("filesystem_inodes_free", "Number of free inodes of a configured filesystem.", MetricType::Gauge);
+    let mut fs_inodes_used = MetricFamily::new("filesystem_inodes_usage_percent", "Used inodes of a configured filesystem in percent.", MetricType::Gauge);
+    let mut fs_read_only = MetricFamily::new("filesystem_read_only", "Whether a configured filesystem is mounted read-only (1) or not (0).", MetricType::Gauge);
+    let mut fs_info = MetricFamily::new("filesystem_info", "Device, type and removability of a configured filesystem, always 1.", MetricType::Gauge);
+    for fs in &stats.file_systems_stats {
+        let labels = vec![("fs_name", fs.fs_name.clone()), ("fs_mount_point", fs.fs_mount_point.clone())];
+        fs_size.push(labels.clone(), fs.fs_total_bytes.map(|bytes| bytes as f64));
+        fs_used_bytes.push(labels.clone(), fs.fs_used_bytes.map(|bytes| bytes as f64));
+        fs_available.push(labels.clone(), fs.fs_available_bytes.map(|bytes| bytes as f64));
+        fs_inodes.push(labels.clone(), fs.fs_inodes_total.map(|inodes| inodes as f64));
+        fs_inodes_free.push(labels.clone(), fs.fs_inodes_free.map(|inodes| inodes as f64));
+        fs_inodes_used.push(labels.clone(), fs.fs_inodes_used_percent);
+        fs_read_only.push(labels.clone(), fs.fs_read_only.map(|read_only| if read_only { 1. } else { 0. }));
+        if let (Some(device), Some(fs_type)) = (&fs.fs_device, &fs.fs_type) {
+            let mut info_labels = labels;
+            info_labels.push(("device", device.clone()));
+            info_labels.push(("fs_type", fs_type.clone()));
+            info_labels.push(("removable", fs.fs_removable.unwrap_or(false).to_string()));
+            fs_info.push(info_labels, Some(1.));
+        }
+    }
+    fs_size.render(&mut out);
+    fs_used_bytes.render(&mut out);
+    fs_available.render(&mut out);
+    fs_inodes.render(&mut out);
+    fs_inodes_free.render(&mut out);
+    fs_inodes_used.render(&mut out);
+    fs_read_only.render(&mut out);
+    fs_info.render(&mut out);
+
+    let mut fs_growth = MetricFamily::new("filesystem_growth_percent_per_hour", "Growth rate of the used space of a configured filesystem over the forecast window.", MetricType::Gauge);
+    let mut fs_hours_to_full = MetricFamily::new("filesystem_hours_to_full", "Estimated hours until a configured filesystem is full at its current growth rate.", MetricType::Gauge);
+    for fs in &stats.file_systems_stats {
+        let labels = vec![("fs_name", fs.fs_name.clone()), ("fs_mount_point", fs.fs_mount_point.clone())];
+        fs_growth.push(labels.clone(), fs.fs_growth_percent_per_hour);
+        fs_hours_to_full.push(labels, fs.fs_hours_to_full);
+    }
+    fs_growth.render(&mut out);
+    fs_hours_to_full.render(&mut out);
+
+    let mut disk_read_bytes = MetricFamily::new("block_device_read_bytes_per_second", "Bytes read per second from a block device over the last polling interval.", MetricType::Gauge);
+    let mut disk_write_bytes = MetricFamily::new("block_device_write_bytes_per_second", "Bytes written per second to a block device over the last polling interval.", MetricType::Gauge);
+    let mut disk_read_iops = MetricFamily::new("block_device_read_iops", "Read requests per second completed by a block device.", MetricType::Gauge);
+    let mut disk_write_iops = MetricFamily::new("block_device_write_iops", "Write requests per second completed by a block device.", MetricType::Gauge);
+    let mut disk_queue_time = MetricFamily::new("block_device_queue_time_milliseconds", "Average time a request to a block device spent queued and being served.", MetricType::Gauge);
+    let mut disk_util = MetricFamily::new("block_device_utilization_percent", "Share of the time a block device was busy serving requests in percent.", MetricType::Gauge);
+    for device in &stats.block_devices_stats {
+        let labels = vec![("device", device.device.clone())];
+        disk_read_bytes.push(labels.clone(), device.read_bytes_per_sec);
+        disk_write_bytes.push(labels.clone(), device.write_bytes_per_sec);
+        disk_read_iops.push(labels.clone(), device.read_iops);
+        disk_write_iops.push(labels.clone(), device.write_iops);
+        disk_queue_time.push(labels.clone(), device.queue_time_ms);
+        disk_util.push(labels, device.util_percent);
+    }
+    disk_read_bytes.render(&mut out);
+    disk_write_bytes.render(&mut out);
+    disk_read_iops.render(&mut out);
+    disk_write_iops.render(&mut out);
+    disk_queue_time.render(&mut out);
+    disk_util.render(&mut out);
+
+    // The top processes by CPU and by memory, each process once
+    let mut process_cpu = MetricFamily::new("process_cpu_usage_percent", "CPU usage of a top process in percent of a single core.", MetricType::Gauge);
+    let mut process_rss = MetricFamily::new("process_resident_memory_bytes", "Resident memory of a top process in bytes.", MetricType::Gauge);
+    let mut process_read = MetricFamily::new("process_disk_read_bytes_per_second", "Bytes read per second from disk by a top process.", MetricType::Gauge);
+    let mut process_write = MetricFamily::new("process_disk_write_bytes_per_second", "Bytes written per second to disk by a top process.", MetricType::Gauge);
+    let mut reported_pids: Vec<u32> = Vec::new();
+    for process in stats.processes_stats.top_cpu.iter().chain(&stats.processes_stats.top_memory) {
+        if reported_pids.contains(&process.pid) {
+            continue;
+        }
+        reported_pids.push(process.pid);
+        let labels = vec![
+            ("pid", process.pid.to_string()),
+            ("name", process.name.clone()),
+            ("user", process.user.clone().unwrap_or_default()),
+        ];
+        process_cpu.push(labels.clone(), Some(process.cpu_percent));
+        process_rss.push(labels.clone(), Some(process.rss_bytes as f64));
+        process_read.push(labels.clone(), process.disk_read_bytes_per_sec);
+        process_write.push(labels, process.disk_write_bytes_per_sec);
+    }
+    process_cpu.render(&mut out);
+    process_rss.render(&mut out);
+    process_read.render(&mut out);
+    process_write.render(&mut out);
+
+    let mut watched_running = MetricFamily::new("watched_process_running", "Whether a watched process is running (1) or gone (0).", MetricType::Gauge);
+    let mut watched_count = MetricFamily::new("watched_process_instances", "Number of running instances of a watched process.", MetricType::Gauge);
+    let mut watched_cpu = MetricFamily::new("watched_process_cpu_usage_percent", "CPU usage of all the instances of a watched process in percent of a single core.", MetricType::Gauge);
+    let mut watched_rss = MetricFamily::new("watched_process_resident_memory_bytes", "Resident memory of all the instances of a watched process in bytes.", MetricType::Gauge);
+    let mut watched_restarts = MetricFamily::new("watched_process_restarts_total", "Restarts of a watched process detected since the exporter started.", MetricType::Counter);
+    for process in &stats.processes_stats.watched {
+        let labels = vec![("process", process.name.clone())];
+        watched_running.push(labels.clone(), Some(if process.running { 1. } else { 0. }));
+        watched_count.push(labels.clone(), Some(process.count as f64));
+        watched_cpu.push(labels.clone(), Some(process.cpu_percent));
+        watched_rss.push(labels.clone(), Some(process.rss_bytes as f64));
+        watched_restarts.push(labels, Some(process.restarts as f64));
+    }
+    watched_running.render(&mut out);
+    watched_count.render(&mut out);
+    watched_cpu.render(&mut out);
+    watched_rss.render(&mut out);
+    watched_restarts.render(&mut out);
+
+    let mut node_basic = BasicFamilies::new("kubernetes_node_");
+    let mut node_pods = MetricFamily::new("kubernetes_node_pods", "Number of pods running on the node, excluding the ignored namespaces.", MetricType::Gauge);
+    let mut node_pods_max = MetricFamily::new("kubernetes_node_pods_max", "Maximum number of pods the node can run.", MetricType::Gauge);
+    for kube in &stats.kubernetes_stats {
+        for node in &kube.node_stats {
+            let labels = vec![
+                ("node_name", node.node_name.clone()),
+                ("node_role", node.node_role.clone()),
+                ("node_ip", node.node_ip.clone()),
+            ];
+            node_basic.push(&node.node_basic_stats, &labels, "", "");
+            node_pods.push(labels.clone(), Some(node.node_pods.len() as f64));
+            node_pods_max.push(labels, node.node_pods_max.map(|max| max as f64));
+        }
+    }
+    node_basic.render(&mut out);
+    node_pods.render(&mut out);
+    node_pods_max.render(&mut out);
+
+    out
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::{sample, NetworkInterfaceStats};
+
+    fn rendered(family: &MetricFamily) -> String {
+        let mut out = String::new();
+        family.render(&mut out);
+        out
+    }
+
+    fn interface(iface: &str, down_kbps: f64, rx_bytes_total: u64) -> NetworkInterfaceStats {
+        serde_json::from_value(serde_json::json!({
+            "iface": iface,
+            "down_kbps": down_kbps,
+            "up_kbps": 0.,
+            "rx_bytes_total": rx_bytes_total,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn renders_one_header_per_family() {
+        let mut family = MetricFamily::new("load_average", "System load average over the period.", MetricType::Gauge);
+        family.push(vec![("period", String::from("1m"))], Some(0.5));
+        family.push(vec![("period", String::from("5m"))], None);
+        family.push(vec![("period", String::from("15m"))], Some(0.25));
+        assert_eq!(
+            rendered(&family),
+            concat!(
+                "# HELP stats_exporter_load_average System load average over the period.\n",
+                "# TYPE stats_exporter_load_average gauge\n",
+                "stats_exporter_load_average{period=\"1m\"} 0.5\n",
+                "stats_exporter_load_average{period=\"15m\"} 0.25\n",
+            )
+        );
+    }
+
+    #[test]
+    fn skips_the_values_not_collected() {
+        let mut family = MetricFamily::new("temperature_celsius", "Temperature.", MetricType::Gauge);
+        family.push(Vec::new(), None);
+        assert_eq!(rendered(&family), "");
+
+        let mut family = MetricFamily::new("uptime_seconds", "Uptime.", MetricType::Gauge);
+        family.push(Vec::new(), Some(42.));
+        assert_eq!(rendered(&family).lines().last(), Some("stats_exporter_uptime_seconds 42"));
+    }
+
+    #[test]
+    fn renders_counters() {
+        let mut family = MetricFamily::new("network_interface_receive_bytes_total", "Bytes received.", MetricType::Counter);
+        family.push(vec![("iface", String::from("eth0"))], Some(1024.));
+        assert!(rendered(&family).contains("# TYPE stats_exporter_network_interface_receive_bytes_total counter\n"));
+    }
+
+    #[test]
+    fn escapes_the_label_values() {
+        assert_eq!(
+            render_labels(&[("fs_name", String::from("a\\b \"c\"\nd")), ("fs_mount_point", String::from("/"))]),
+            "{fs_name=\"a\\\\b \\\"c\\\"\\nd\",fs_mount_point=\"/\"}"
+        );
+        assert_eq!(render_labels(&[]), "");
+    }
+
+    #[test]
+    fn renders_a_sample() {
+        let mut stats = sample(1700000000, 42.5);
+        stats.basic_stats.net_down_kbps = Some(30.);
+        stats.network_stats = vec![interface("eth0", 10., 1000), interface("wlan0", 20., 2000)];
+        let out = render_metrics(Some(&stats), "");
+
+        assert!(out.contains("stats_exporter_memory_usage_percent 42.5\n"));
+        // The host rates are labelled with the interfaces they add up
+        assert!(out.contains("stats_exporter_network_receive_kbps{iface=\"eth0,wlan0\"} 30\n"));
+        assert!(out.contains("stats_exporter_network_interface_receive_kbps{iface=\"wlan0\"} 20\n"));
+        assert!(out.contains("# TYPE stats_exporter_network_interface_receive_bytes_total counter\n"));
+        assert!(out.contains("stats_exporter_network_interface_receive_bytes_total{iface=\"eth0\"} 1000\n"));
+        // Nothing about what was not collected
+        assert!(!out.contains("stats_exporter_cpu_usage_percent"));
+        assert!(!out.contains("stats_exporter_temperature_celsius"));
+
+        // Every sample follows the header of its family, and a family is only described once
+        let mut described: Vec<&str> = Vec::new();
+        for line in out.lines() {
+            if let Some(header) = line.strip_prefix("# TYPE ") {
+                let name = header.split(' ').next().unwrap();
+                assert!(!described.contains(&name), "{} described twice", name);
+                described.push(name);
+            } else if !line.starts_with('#') {
+                let name = line.split(['{', ' ']).next().unwrap();
+                assert_eq!(described.last(), Some(&name));
+            }
+        }
+    }
+
+    #[test]
+    fn renders_only_the_build_info_without_a_sample() {
+        let out = render_metrics(None, "");
+        assert!(out.starts_with("# HELP stats_exporter_build_info "));
+        assert_eq!(out.lines().filter(|line| !line.starts_with('#')).count(), 1);
+    }
+}
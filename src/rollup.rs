@@ -0,0 +1,373 @@
+// Downsampled history: every sample is folded into fixed windows of 1 minute,
+// 15 minutes and 1 hour. A closed window keeps the min, max, average and p95
+// of the gauges of the host, filesystems, network, block devices and watched
+// processes sections (see v2::Stats::numeric_fields): counters (`*_total`),
+// constants such as the boot time or the MTU and the per-core values are left
+// out, and a window holds at most MAX_WINDOW_FIELDS fields so that hosts with
+// many short lived interfaces or disks do not grow it without bound. When a
+// storage path is configured, the closed windows of each tier are also
+// appended to `rollup-<tier>.jsonl` there.
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::v2;
+
+// ------------------------------------------------------------------
+
+// (name, window seconds, windows kept): one day, one week and ninety days
+const TIERS: [(&str, u64, usize); 3] = [
+    ("1m", 60, 1440),
+    ("15m", 900, 672),
+    ("1h", 3600, 2160),
+];
+
+// Most windows a query should return before a coarser tier is preferred
+const MAX_QUERY_WINDOWS: u64 = 1500;
+
+// Fields aggregated in a window, the ones seen first win
+const MAX_WINDOW_FIELDS: usize = 512;
+
+// Numeric fields that are not gauges, besides the `*_total` counters
+const NOT_GAUGES: [&str; 4] = ["uptime_secs", "boot_time", "mtu", "restarts"];
+
+const ROLLUP_PREFIX: &str = "rollup-";
+const ROLLUP_SUFFIX: &str = ".jsonl";
+
+// ------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Aggregate {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub p95: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Window {
+    pub start: u64,                             // unix timestamp, aligned on the window length
+    pub end: u64,                               // exclusive
+    pub samples: usize,
+    pub fields: BTreeMap<String, Aggregate>,
+}
+
+// Values gathered for the window that is still open
+struct OpenWindow {
+    start: u64,
+    samples: usize,
+    values: BTreeMap<String, Vec<f64>>,
+}
+
+impl OpenWindow {
+    fn close(self, secs: u64) -> Window {
+        let fields = self
+            .values
+            .into_iter()
+            .map(|(name, values)| (name, aggregate(values)))
+            .collect();
+        Window { start: self.start, end: self.start + secs, samples: self.samples, fields }
+    }
+}
+
+struct Tier {
+    name: &'static str,
+    secs: u64,
+    capacity: usize,
+    windows: VecDeque<Window>,
+    open: Option<OpenWindow>,
+    file: Option<PathBuf>,
+    lines_in_file: usize,
+}
+
+impl Tier {
+    fn add(&mut self, timestamp: u64, fields: &[(String, f64)]) {
+        let start = timestamp - timestamp % self.secs;
+        if self.open.as_ref().is_some_and(|open| open.start != start) {
+            if let Some(open) = self.open.take() {
+                self.push(open.close(self.secs));
+            }
+        }
+
+        let open = self.open.get_or_insert_with(|| OpenWindow { start, samples: 0, values: BTreeMap::new() });
+        open.samples += 1;
+        for (name, value) in fields {
+            if open.values.len() >= MAX_WINDOW_FIELDS && !open.values.contains_key(name) {
+                continue;
+            }
+            open.values.entry(name.clone()).or_default().push(*value);
+        }
+    }
+
+    fn push(&mut self, window: Window) {
+        self.windows.push_back(window.clone());
+        while self.windows.len() > self.capacity {
+            self.windows.pop_front();
+        }
+        self.persist(&window);
+    }
+
+    fn persist(&mut self, window: &Window) {
+        let file = match &self.file {
+            Some(file) => file.clone(),
+            None => return,
+        };
+        // Rewrite the file once it holds twice the windows kept, appending otherwise
+        let result = if self.lines_in_file >= self.capacity * 2 {
+            self.lines_in_file = self.windows.len();
+            write_windows(&file, self.windows.iter(), false)
+        } else {
+            self.lines_in_file += 1;
+            write_windows(&file, std::iter::once(window), true)
+        };
+        if let Err(e) = result {
+            eprintln!("[!] Could not persist {} rollup: {}", self.name, e);
+        }
+    }
+
+    // Closed windows overlapping [since, until]
+    fn select(&self, since: Option<u64>, until: Option<u64>) -> Vec<Window> {
+        self.windows
+            .iter()
+            .filter(|w| since.is_none_or(|since| w.end > since))
+            .filter(|w| until.is_none_or(|until| w.start <= until))
+            .cloned()
+            .collect()
+    }
+}
+
+// ------------------------------------------------------------------
+
+pub struct Rollups {
+    tiers: Vec<Tier>,
+}
+
+impl Rollups {
+    // Build the tiers, reloading their windows from the storage path if any
+    pub fn new(storage_path: Option<&str>) -> Self {
+        let mut tiers: Vec<Tier> = Vec::new();
+        for (name, secs, capacity) in TIERS {
+            let file = storage_path.map(|path| PathBuf::from(path).join(format!("{}{}{}", ROLLUP_PREFIX, name, ROLLUP_SUFFIX)));
+            let mut windows: VecDeque<Window> = VecDeque::new();
+            let mut lines_in_file = 0;
+            if let Some(file) = &file {
+                let loaded = read_windows(file);
+                lines_in_file = loaded.len();
+                windows.extend(loaded.into_iter().rev().take(capacity).rev());
+            }
+            tiers.push(Tier { name, secs, capacity, windows, open: None, file, lines_in_file });
+        }
+        Rollups { tiers }
+    }
+
+    pub fn tier_names() -> Vec<&'static str> {
+        TIERS.iter().map(|(name, _, _)| *name).collect()
+    }
+
+    pub fn add(&mut self, stats: &v2::Stats) {
+        let fields: Vec<(String, f64)> = stats
+            .numeric_fields()
+            .into_iter()
+            .filter(|(name, _)| is_gauge(name))
+            .collect();
+        for tier in self.tiers.iter_mut() {
+            tier.add(stats.timestamp, &fields);
+        }
+    }
+
+    // The windows of the requested tier, or of the finest tier that still holds
+    // `since` and answers with at most MAX_QUERY_WINDOWS windows
+    pub fn query(&self, tier: Option<&str>, since: Option<u64>, until: Option<u64>, now: u64) -> Result<(&'static str, u64, Vec<Window>), String> {
+        let tier = match tier {
+            Some(requested) => self.tiers.iter().find(|t| t.name == requested).ok_or_else(|| {
+                format!("unknown tier `{}`, expected any of: {}", requested, Rollups::tier_names().join(", "))
+            })?,
+            None => {
+                let range_end = until.unwrap_or(now).min(now);
+                self.tiers
+                    .iter()
+                    .find(|t| match since {
+                        Some(since) => {
+                            now.saturating_sub(since) <= t.secs * t.capacity as u64
+                                && range_end.saturating_sub(since) / t.secs <= MAX_QUERY_WINDOWS
+                        }
+                        None => false,
+                    })
+                    .unwrap_or_else(|| self.tiers.last().unwrap())
+            }
+        };
+        Ok((tier.name, tier.secs, tier.select(since, until)))
+    }
+
+    // (window middle, average) of a field over [since, now], from the tier a query would pick
+    pub fn series(&self, field: &str, since: u64, now: u64) -> Vec<(u64, f64)> {
+        match self.query(None, Some(since), None, now) {
+            Ok((_, secs, windows)) => windows
+                .iter()
+                .filter_map(|w| w.fields.get(field).map(|agg| (w.start + secs / 2, agg.avg)))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+// ------------------------------------------------------------------
+
+fn is_gauge(name: &str) -> bool {
+    let field = name.rsplit('.').next().unwrap_or(name);
+    // List items (`basic_stats.cpu_cores_percent.0`) end with their index
+    field.parse::<usize>().is_err() && !field.ends_with("_total") && !NOT_GAUGES.contains(&field)
+}
+
+fn aggregate(mut values: Vec<f64>) -> Aggregate {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let count = values.len();
+    // Nearest-rank percentile
+    let p95_rank = ((count as f64 * 0.95).ceil() as usize).clamp(1, count);
+    Aggregate {
+        min: values[0],
+        max: values[count - 1],
+        avg: values.iter().sum::<f64>() / count as f64,
+        p95: values[p95_rank - 1],
+    }
+}
+
+fn read_windows(file: &PathBuf) -> Vec<Window> {
+    match File::open(file) {
+        Ok(f) => BufReader::new(f)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<Window>(&line).ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn write_windows<'a>(file: &PathBuf, windows: impl Iterator<Item = &'a Window>, append: bool) -> Result<(), String> {
+    let mut content = String::new();
+    for window in windows {
+        content.push_str(&serde_json::to_string(window).map_err(|e| e.to_string())?);
+        content.push('\n');
+    }
+    if append {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .map_err(|e| format!("could not open `{}`: {}", file.display(), e))?;
+        f.write_all(content.as_bytes()).map_err(|e| e.to_string())
+    } else {
+        // Write aside first so that a crash never leaves a truncated file
+        let tmp = file.with_extension("tmp");
+        fs::write(&tmp, content).map_err(|e| format!("could not write `{}`: {}", tmp.display(), e))?;
+        fs::rename(&tmp, file).map_err(|e| format!("could not replace `{}`: {}", file.display(), e))
+    }
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::sample;
+
+
+    #[test]
+    fn aggregates_the_values() {
+        let agg = aggregate(vec![3., 1., 2.]);
+        assert_eq!((agg.min, agg.max, agg.avg, agg.p95), (1., 3., 2., 3.));
+
+        let agg = aggregate(vec![42.]);
+        assert_eq!((agg.min, agg.max, agg.avg, agg.p95), (42., 42., 42., 42.));
+
+        // Nearest rank: the 95th of 100 values, the 19th of 20
+        let agg = aggregate((1..=100).rev().map(f64::from).collect());
+        assert_eq!((agg.min, agg.max, agg.avg, agg.p95), (1., 100., 50.5, 95.));
+        let agg = aggregate((1..=20).map(f64::from).collect());
+        assert_eq!(agg.p95, 19.);
+    }
+
+    #[test]
+    fn aggregates_only_the_gauges() {
+        assert!(is_gauge("basic_stats.ram_percent"));
+        assert!(is_gauge("network_stats.eth0.down_kbps"));
+        assert!(is_gauge("processes_stats.watched.nginx.running"));
+        assert!(!is_gauge("basic_stats.cpu_cores_percent.3"));
+        assert!(!is_gauge("basic_stats.boot_time"));
+        assert!(!is_gauge("network_stats.eth0.rx_bytes_total"));
+        assert!(!is_gauge("network_stats.eth0.mtu"));
+        assert!(!is_gauge("processes_stats.watched.nginx.restarts"));
+
+        let mut tier = Tier { name: "1m", secs: 60, capacity: 10, windows: VecDeque::new(), open: None, file: None, lines_in_file: 0 };
+        let fields: Vec<(String, f64)> = (0..MAX_WINDOW_FIELDS + 10).map(|index| (format!("network_stats.veth{}.down_kbps", index), 1.)).collect();
+        tier.add(0, &fields);
+        tier.add(60, &fields[..1]);
+        assert_eq!(tier.windows[0].fields.len(), MAX_WINDOW_FIELDS);
+    }
+
+    #[test]
+    fn closes_a_window_once_the_next_one_starts() {
+        let mut rollups = Rollups::new(None);
+        for (timestamp, ram) in [(120, 10.), (150, 30.), (179, 20.)] {
+            rollups.add(&sample(timestamp, ram));
+        }
+        let (_, _, windows) = rollups.query(Some("1m"), None, None, 179).unwrap();
+        assert!(windows.is_empty());
+
+        rollups.add(&sample(180, 50.));
+        let (name, secs, windows) = rollups.query(Some("1m"), None, None, 180).unwrap();
+        assert_eq!((name, secs, windows.len()), ("1m", 60, 1));
+        assert_eq!((windows[0].start, windows[0].end, windows[0].samples), (120, 180, 3));
+        let ram = &windows[0].fields["basic_stats.ram_percent"];
+        assert_eq!((ram.min, ram.max, ram.avg), (10., 30., 20.));
+    }
+
+    #[test]
+    fn picks_the_finest_tier_for_the_range() {
+        // Ten days of one sample every 10 minutes
+        let now = 10 * 86400;
+        let mut rollups = Rollups::new(None);
+        for timestamp in (0..=now).step_by(600) {
+            rollups.add(&sample(timestamp, 50.));
+        }
+        let tier = |since: Option<u64>, until: Option<u64>| rollups.query(None, since, until, now).unwrap().0;
+
+        // The 1m tier holds a day
+        assert_eq!(tier(Some(now - 3600), None), "1m");
+        assert_eq!(tier(Some(now - 86400), None), "1m");
+        assert_eq!(tier(Some(now - 86400 - 60), None), "15m");
+        // The 15m tier holds a week
+        assert_eq!(tier(Some(now - 6 * 86400), None), "15m");
+        assert_eq!(tier(Some(now - 8 * 86400), None), "1h");
+        // Data older than a day is no longer in the 1m tier, however short the range
+        assert_eq!(tier(Some(now - 2 * 86400), Some(now - 2 * 86400 + 600)), "15m");
+        // Without a start, the coarsest tier
+        assert_eq!(tier(None, None), "1h");
+
+        let (_, _, windows) = rollups.query(Some("1h"), Some(now - 7200), Some(now - 3600), now).unwrap();
+        assert_eq!(windows.iter().map(|w| w.start).collect::<Vec<u64>>(), vec![now - 7200, now - 3600]);
+
+        assert!(rollups.query(Some("1d"), None, None, now).is_err_and(|e| e.contains("unknown tier `1d`")));
+    }
+
+    #[test]
+    fn keeps_the_closed_windows_on_disk() {
+        let dir = std::env::temp_dir().join(format!("stats-exporter-rollups-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let storage_path = dir.to_string_lossy().to_string();
+
+        let mut rollups = Rollups::new(Some(&storage_path));
+        for timestamp in [0, 30, 60, 90, 120] {
+            rollups.add(&sample(timestamp, timestamp as f64));
+        }
+        let reloaded = Rollups::new(Some(&storage_path));
+        let (_, _, windows) = reloaded.query(Some("1m"), None, None, 120).unwrap();
+        assert_eq!(windows.iter().map(|w| (w.start, w.samples)).collect::<Vec<(u64, usize)>>(), vec![(0, 2), (60, 2)]);
+        assert_eq!(windows[1].fields["basic_stats.ram_percent"].avg, 75.);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
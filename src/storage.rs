@@ -0,0 +1,254 @@
+// Persistent history: every sample is appended as one JSON line to a
+// segment file `stats-<unix start time>.jsonl` in the storage directory.
+// A new segment is started every SEGMENT_SECS, and whole segments are
+// deleted once they are older than the retention period.
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{now_secs, v2};
+
+// ------------------------------------------------------------------
+
+const SEGMENT_SECS: u64 = 3600;
+const SEGMENT_PREFIX: &str = "stats-";
+const SEGMENT_SUFFIX: &str = ".jsonl";
+
+// ------------------------------------------------------------------
+
+pub struct SegmentStore {
+    dir: PathBuf,
+    retention_secs: Option<u64>,
+    current: Option<(u64, File)>,
+}
+
+impl SegmentStore {
+    pub fn open(storage_path: &str, retention_hours: Option<u64>) -> Result<Self, String> {
+        let dir = PathBuf::from(storage_path);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("could not create storage directory `{}`: {}", storage_path, e))?;
+        Ok(SegmentStore {
+            dir,
+            retention_secs: retention_hours.map(|hours| hours * 3600),
+            current: None,
+        })
+    }
+
+    // Load the last `count` samples still within the retention period, oldest first
+    pub fn load_latest(&self, count: usize) -> Vec<v2::Stats> {
+        self.load_latest_at(count, now_secs())
+    }
+
+    fn load_latest_at(&self, count: usize, now: u64) -> Vec<v2::Stats> {
+        let oldest_ts = self.oldest_kept_ts(now);
+        let mut segments = self.list_segments();
+        segments.reverse();
+
+        let mut loaded: Vec<v2::Stats> = Vec::new();
+        for (_, segment) in segments {
+            if loaded.len() >= count {
+                break;
+            }
+            let mut records = read_segment(&segment, oldest_ts);
+            // Keep the newest samples of the segment that still fit
+            let excess = (records.len() + loaded.len()).saturating_sub(count);
+            records.drain(..excess);
+            records.append(&mut loaded);
+            loaded = records;
+        }
+        loaded
+    }
+
+    // Append a sample to the current segment, rolling over to a new one when due
+    pub fn append(&mut self, stats: &v2::Stats) -> Result<(), String> {
+        self.append_at(stats, now_secs())
+    }
+
+    fn append_at(&mut self, stats: &v2::Stats, now: u64) -> Result<(), String> {
+        let rollover = match &self.current {
+            Some((start, _)) => now >= start + SEGMENT_SECS,
+            None => true,
+        };
+        if rollover {
+            let path = self.dir.join(format!("{}{}{}", SEGMENT_PREFIX, now, SEGMENT_SUFFIX));
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("could not open segment `{}`: {}", path.display(), e))?;
+            self.current = Some((now, file));
+            self.prune(now);
+        }
+
+        let mut line = serde_json::to_string(stats)
+            .map_err(|e| format!("could not encode sample: {}", e))?;
+        line.push('\n');
+        if let Some((_, file)) = self.current.as_mut() {
+            file.write_all(line.as_bytes())
+                .map_err(|e| format!("could not write sample: {}", e))?;
+        }
+        Ok(())
+    }
+
+    // Delete the segments that only hold samples older than the retention period
+    fn prune(&self, now: u64) {
+        let oldest_ts = match self.oldest_kept_ts(now) {
+            Some(ts) => ts,
+            None => return,
+        };
+        let segments = self.list_segments();
+        for (index, (start, segment)) in segments.iter().enumerate() {
+            // A segment ends where the next one starts (or after SEGMENT_SECS)
+            let end = match segments.get(index + 1) {
+                Some((next_start, _)) => *next_start,
+                None => start + SEGMENT_SECS,
+            };
+            if end < oldest_ts {
+                if let Err(e) = fs::remove_file(segment) {
+                    eprintln!("[!] Could not remove expired segment `{}`: {}", segment.display(), e);
+                }
+            }
+        }
+    }
+
+    fn oldest_kept_ts(&self, now: u64) -> Option<u64> {
+        self.retention_secs.map(|retention| now.saturating_sub(retention))
+    }
+
+    // Segment files sorted by start time
+    fn list_segments(&self) -> Vec<(u64, PathBuf)> {
+        let mut segments: Vec<(u64, PathBuf)> = Vec::new();
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for entry in entries.flatten() {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let start = file_name
+                    .strip_prefix(SEGMENT_PREFIX)
+                    .and_then(|rest| rest.strip_suffix(SEGMENT_SUFFIX))
+                    .and_then(|ts| ts.parse::<u64>().ok());
+                if let Some(start) = start {
+                    segments.push((start, entry.path()));
+                }
+            }
+        }
+        segments.sort_by_key(|(start, _)| *start);
+        segments
+    }
+}
+
+// ------------------------------------------------------------------
+
+// Read the samples of a segment, skipping lines that cannot be parsed
+// (e.g. the last one, if the exporter was killed while writing it)
+fn read_segment(segment: &Path, oldest_ts: Option<u64>) -> Vec<v2::Stats> {
+    let file = match File::open(segment) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("[!] Could not read segment `{}`: {}", segment.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut samples: Vec<v2::Stats> = Vec::new();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        if let Ok(stats) = serde_json::from_str::<v2::Stats>(&line) {
+            if oldest_ts.is_none_or(|oldest| stats.timestamp >= oldest) {
+                samples.push(stats);
+            }
+        }
+    }
+    samples
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::sample;
+
+    const NOW: u64 = 1700000000;
+
+    // An empty storage directory of its own for each test
+    fn storage_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("stats-exporter-storage-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_string_lossy().to_string()
+    }
+
+    fn timestamps(samples: &[v2::Stats]) -> Vec<u64> {
+        samples.iter().map(|stats| stats.timestamp).collect()
+    }
+
+    #[test]
+    fn reloads_the_history_after_a_restart() {
+        let dir = storage_dir("reload");
+        let mut store = SegmentStore::open(&dir, None).unwrap();
+        for offset in 0..3 {
+            store.append_at(&sample(NOW + offset, 50.), NOW + offset).unwrap();
+        }
+        drop(store);
+
+        let store = SegmentStore::open(&dir, None).unwrap();
+        assert_eq!(timestamps(&store.load_latest_at(10, NOW + 3)), vec![NOW, NOW + 1, NOW + 2]);
+        assert_eq!(timestamps(&store.load_latest_at(2, NOW + 3)), vec![NOW + 1, NOW + 2]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rolls_over_to_a_new_segment() {
+        let dir = storage_dir("rollover");
+        let mut store = SegmentStore::open(&dir, None).unwrap();
+        for ts in [NOW, NOW + 10, NOW + SEGMENT_SECS - 1, NOW + SEGMENT_SECS, NOW + SEGMENT_SECS + 10] {
+            store.append_at(&sample(ts, 50.), ts).unwrap();
+        }
+        let starts: Vec<u64> = store.list_segments().iter().map(|(start, _)| *start).collect();
+        assert_eq!(starts, vec![NOW, NOW + SEGMENT_SECS]);
+
+        // The newest samples are taken across segments, oldest first
+        let loaded = store.load_latest_at(4, NOW + SEGMENT_SECS + 10);
+        assert_eq!(timestamps(&loaded), vec![NOW + 10, NOW + SEGMENT_SECS - 1, NOW + SEGMENT_SECS, NOW + SEGMENT_SECS + 10]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prunes_the_segments_past_the_retention() {
+        let dir = storage_dir("prune");
+        let mut store = SegmentStore::open(&dir, Some(1)).unwrap();
+        let old = NOW - 4 * SEGMENT_SECS;
+        let previous = NOW - SEGMENT_SECS - 600;
+        let current = NOW - 300;
+        for ts in [old, old + SEGMENT_SECS, previous, NOW - 1200, current, NOW] {
+            store.append_at(&sample(ts, 50.), ts).unwrap();
+        }
+
+        // The segments that ended more than an hour ago are removed, the previous one is kept
+        let starts: Vec<u64> = store.list_segments().iter().map(|(start, _)| *start).collect();
+        assert_eq!(starts, vec![previous, current]);
+        // Samples past the retention are not reloaded, even while their segment is kept
+        assert_eq!(timestamps(&store.load_latest_at(10, NOW)), vec![NOW - 1200, current, NOW]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skips_corrupt_and_truncated_lines() {
+        let dir = storage_dir("corrupt");
+        let mut store = SegmentStore::open(&dir, None).unwrap();
+        store.append_at(&sample(NOW, 50.), NOW).unwrap();
+        store.append_at(&sample(NOW + 1, 50.), NOW + 1).unwrap();
+        drop(store);
+
+        // A garbled line in the middle and a sample cut short by a crash at the end
+        let segment = Path::new(&dir).join(format!("{}{}{}", SEGMENT_PREFIX, NOW, SEGMENT_SUFFIX));
+        let mut content = fs::read_to_string(&segment).unwrap();
+        content.insert_str(content.find('\n').unwrap() + 1, "not json\n");
+        let last = serde_json::to_string(&sample(NOW + 2, 50.)).unwrap();
+        content.push_str(&last[..last.len() / 2]);
+        fs::write(&segment, content).unwrap();
+
+        let mut store = SegmentStore::open(&dir, None).unwrap();
+        assert_eq!(timestamps(&store.load_latest_at(10, NOW + 3)), vec![NOW, NOW + 1]);
+        // New samples go to a new segment, not after the cut line
+        store.append_at(&sample(NOW + 3, 50.), NOW + 3).unwrap();
+        assert_eq!(timestamps(&store.load_latest_at(10, NOW + 3)), vec![NOW, NOW + 1, NOW + 3]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+// Helpers for the endpoints that push live samples to their clients
+// (/stream with Server-Sent Events, /ws with a WebSocket): a client picks
+// the sections of a sample it wants to receive, optionally narrows the
+// filesystems down to some names, and sets how often it wants an update.
+use axum::extract::ws::{Message, WebSocket};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::v2;
+
+// ------------------------------------------------------------------
+
+const SECTION_KEYS: [(&str, &str); 6] = [
+    ("basic", "basic_stats"),
+    ("filesystems", "file_systems_stats"),
+    ("network", "network_stats"),
+    ("blockdevices", "block_devices_stats"),
+    ("processes", "processes_stats"),
+    ("kubernetes", "kubernetes_stats"),
+];
+
+// ------------------------------------------------------------------
+
+#[derive(Clone)]
+pub struct Sections {
+    names: Vec<&'static str>,
+}
+
+impl Sections {
+    pub fn all() -> Self {
+        Sections {
+            names: SECTION_KEYS.iter().map(|(name, _)| *name).collect(),
+        }
+    }
+
+    // Parse a comma separated list of section names ("basic,filesystems")
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut sections = Sections { names: Vec::new() };
+        for requested in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            sections.insert(requested)?;
+        }
+        Ok(sections)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(&name)
+    }
+
+    pub fn insert(&mut self, requested: &str) -> Result<(), String> {
+        let name = lookup_section(requested)?;
+        if !self.names.contains(&name) {
+            self.names.push(name);
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, requested: &str) -> Result<(), String> {
+        let name = lookup_section(requested)?;
+        self.names.retain(|n| *n != name);
+        Ok(())
+    }
+}
+
+fn lookup_section(requested: &str) -> Result<&'static str, String> {
+    match SECTION_KEYS.iter().find(|(name, _)| *name == requested) {
+        Some((name, _)) => Ok(name),
+        None => Err(format!(
+            "unknown section `{}`, expected any of: {}",
+            requested,
+            SECTION_KEYS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+// ------------------------------------------------------------------
+
+// Query parameters accepted by /stream and /ws
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    sections: Option<String>,       // comma separated: basic,filesystems,network,blockdevices,processes,kubernetes (all of them by default)
+    fs_names: Option<String>,       // comma separated fs_name values (all the filesystems by default)
+    interval_secs: Option<u64>,     // minimum seconds between two updates (every sample by default)
+}
+
+// What a client wants to receive
+pub struct Subscription {
+    sections: Sections,
+    fs_names: Option<Vec<String>>,
+    interval_secs: u64,
+    last_sent_ts: Option<u64>,
+}
+
+impl Subscription {
+    pub fn from_query(query: &StreamQuery) -> Result<Self, String> {
+        let sections = match &query.sections {
+            Some(list) => Sections::parse(list)?,
+            None => Sections::all(),
+        };
+        let fs_names = query.fs_names.as_ref().map(|list| {
+            list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+        });
+        Ok(Subscription {
+            sections,
+            fs_names,
+            interval_secs: query.interval_secs.unwrap_or(0),
+            last_sent_ts: None,
+        })
+    }
+
+    // Whether a sample is due, according to the interval asked by the client
+    pub fn is_due(&mut self, stats: &v2::Stats) -> bool {
+        let due = self
+            .last_sent_ts
+            .is_none_or(|last| stats.timestamp >= last + self.interval_secs);
+        if due {
+            self.last_sent_ts = Some(stats.timestamp);
+        }
+        due
+    }
+
+    // Serialize a sample keeping its metadata but only the subscribed sections and filesystems
+    pub fn filter(&self, stats: &v2::Stats) -> Value {
+        let mut value = serde_json::to_value(stats).unwrap_or(Value::Null);
+        if let Value::Object(map) = &mut value {
+            for (name, key) in SECTION_KEYS {
+                if !self.sections.contains(name) {
+                    map.remove(key);
+                }
+            }
+            if let (Some(fs_names), Some(Value::Array(file_systems))) = (&self.fs_names, map.get_mut("file_systems_stats")) {
+                file_systems.retain(|fs| {
+                    fs["fs_name"].as_str().is_some_and(|name| fs_names.iter().any(|n| n == name))
+                });
+            }
+        }
+        value
+    }
+
+    // Apply a command sent by a WebSocket client
+    fn apply(&mut self, command: Command) -> Result<(), String> {
+        match command {
+            Command::Subscribe { sections, fs_names } => {
+                for section in &sections {
+                    self.sections.insert(section)?;
+                }
+                if !fs_names.is_empty() {
+                    self.sections.insert("filesystems")?;
+                    let subscribed = self.fs_names.get_or_insert_with(Vec::new);
+                    for fs_name in fs_names {
+                        if !subscribed.contains(&fs_name) {
+                            subscribed.push(fs_name);
+                        }
+                    }
+                }
+            }
+            Command::Unsubscribe { sections, fs_names } => {
+                let drops_filesystems = sections.iter().any(|section| section == "filesystems");
+                let remaining = self.fs_names.as_ref().map(|subscribed| {
+                    subscribed.iter().filter(|name| !fs_names.contains(name)).cloned().collect::<Vec<_>>()
+                });
+                // Removing every name would silently widen the subscription back to all the
+                // filesystems, the client has to unsubscribe the section to stop receiving them
+                if !drops_filesystems && remaining.as_ref().is_some_and(Vec::is_empty) {
+                    return Err("cannot unsubscribe every fs_name, unsubscribe the `filesystems` section instead".to_string());
+                }
+                for section in &sections {
+                    self.sections.remove(section)?;
+                }
+                self.fs_names = if drops_filesystems { None } else { remaining };
+            }
+            Command::SetInterval { secs } => self.interval_secs = secs,
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> Value {
+        json!({
+            "type": "subscription",
+            "sections": self.sections.names,
+            "fs_names": self.fs_names,
+            "interval_secs": self.interval_secs,
+        })
+    }
+}
+
+// ------------------------------------------------------------------
+
+// Messages a WebSocket client can send, e.g.
+//   {"action": "subscribe", "sections": ["basic"], "fs_names": ["backups"]}
+//   {"action": "unsubscribe", "sections": ["kubernetes"]}
+//   {"action": "set_interval", "secs": 30}
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Command {
+    Subscribe {
+        #[serde(default)]
+        sections: Vec<String>,
+        #[serde(default)]
+        fs_names: Vec<String>,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        sections: Vec<String>,
+        #[serde(default)]
+        fs_names: Vec<String>,
+    },
+    SetInterval {
+        secs: u64,
+    },
+}
+
+// Serve a WebSocket client until it goes away: push the samples matching its
+// subscription and answer every command with the resulting subscription.
+pub async fn serve_websocket(mut socket: WebSocket,
+                             mut receiver: broadcast::Receiver<v2::Stats>,
+                             latest: Option<v2::Stats>,
+                             mut subscription: Subscription) {
+    if send_json(&mut socket, subscription.describe()).await.is_err() {
+        return;
+    }
+    if let Some(sample) = latest {
+        if send_sample(&mut socket, &mut subscription, &sample).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => {
+                let reply = match message {
+                    Some(Ok(Message::Text(text))) => {
+                        match serde_json::from_str::<Command>(&text)
+                            .map_err(|e| e.to_string())
+                            .and_then(|command| subscription.apply(command)) {
+                            Ok(()) => subscription.describe(),
+                            Err(e) => json!({ "type": "error", "message": e }),
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    // Pings are answered by axum, binary messages are ignored
+                    Some(Ok(_)) => continue,
+                };
+                if send_json(&mut socket, reply).await.is_err() {
+                    return;
+                }
+            }
+            sample = receiver.recv() => {
+                match sample {
+                    Ok(sample) => {
+                        if send_sample(&mut socket, &mut subscription, &sample).await.is_err() {
+                            return;
+                        }
+                    }
+                    // Slow clients that lag behind the channel simply skip the samples they missed
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        }
+    }
+}
+
+async fn send_sample(socket: &mut WebSocket, subscription: &mut Subscription, sample: &v2::Stats) -> Result<(), axum::Error> {
+    if !subscription.is_due(sample) {
+        return Ok(());
+    }
+    send_json(socket, json!({ "type": "stats", "stats": subscription.filter(sample) })).await
+}
+
+async fn send_json(socket: &mut WebSocket, value: Value) -> Result<(), axum::Error> {
+    socket.send(Message::Text(value.to_string())).await
+}
+
+// ------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(sections: Option<&str>, fs_names: Option<&str>, interval_secs: Option<u64>) -> Subscription {
+        Subscription::from_query(&StreamQuery {
+            sections: sections.map(String::from),
+            fs_names: fs_names.map(String::from),
+            interval_secs,
+        }).unwrap()
+    }
+
+    fn command(text: &str) -> Command {
+        serde_json::from_str(text).unwrap()
+    }
+
+    // A sample with the given filesystems
+    fn sample_with_filesystems(fs_names: &[&str]) -> v2::Stats {
+        v2::Stats {
+            file_systems_stats: fs_names.iter().map(|name| {
+                serde_json::from_value(json!({ "fs_name": name, "fs_mount_point": format!("/{}", name), "fs_used_percent": 10. })).unwrap()
+            }).collect(),
+            ..v2::sample(1000, 50.)
+        }
+    }
+
+    fn filtered_fs_names(value: &Value) -> Vec<&str> {
+        value["file_systems_stats"].as_array().unwrap().iter().map(|fs| fs["fs_name"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn sends_every_sample_without_an_interval() {
+        let mut subscription = subscription(None, None, None);
+        assert!(subscription.is_due(&v2::sample(1000, 50.)));
+        assert!(subscription.is_due(&v2::sample(1000, 50.)));
+        assert!(subscription.is_due(&v2::sample(1001, 50.)));
+    }
+
+    #[test]
+    fn waits_for_the_interval_between_two_samples() {
+        let mut subscription = subscription(None, None, Some(30));
+        assert!(subscription.is_due(&v2::sample(1000, 50.)));
+        assert!(!subscription.is_due(&v2::sample(1010, 50.)));
+        assert!(!subscription.is_due(&v2::sample(1029, 50.)));
+        assert!(subscription.is_due(&v2::sample(1030, 50.)));
+        // The interval counts from the last sample sent
+        assert!(!subscription.is_due(&v2::sample(1050, 50.)));
+        assert!(subscription.is_due(&v2::sample(1060, 50.)));
+    }
+
+    #[test]
+    fn keeps_the_metadata_and_the_subscribed_sections() {
+        let value = subscription(Some("basic"), None, None).filter(&sample_with_filesystems(&["root"]));
+        assert_eq!(value["timestamp"], 1000);
+        assert_eq!(value["hostname"], "vm");
+        assert_eq!(value["basic_stats"]["ram_percent"], 50.);
+        assert!(value.get("file_systems_stats").is_none());
+        assert!(value.get("network_stats").is_none());
+
+        let value = subscription(None, None, None).filter(&sample_with_filesystems(&["root", "backups"]));
+        for (_, key) in SECTION_KEYS {
+            assert!(value.get(key).is_some(), "{} missing", key);
+        }
+        assert_eq!(filtered_fs_names(&value), vec!["root", "backups"]);
+    }
+
+    #[test]
+    fn narrows_the_filesystems_down_to_the_subscribed_names() {
+        let value = subscription(None, Some("backups, data"), None).filter(&sample_with_filesystems(&["root", "backups", "data"]));
+        assert_eq!(filtered_fs_names(&value), vec!["backups", "data"]);
+    }
+
+    #[test]
+    fn rejects_unknown_sections() {
+        assert!(Subscription::from_query(&StreamQuery { sections: Some("basic,disks".to_string()), fs_names: None, interval_secs: None }).is_err());
+        let mut subscription = subscription(Some("basic"), None, None);
+        assert!(subscription.apply(command(r#"{"action": "subscribe", "sections": ["disks"]}"#)).is_err());
+    }
+
+    #[test]
+    fn applies_the_commands() {
+        let mut subscription = subscription(Some("basic"), None, None);
+        let stats = sample_with_filesystems(&["root", "backups"]);
+
+        // Subscribing to a filesystem name also subscribes to the filesystems section
+        subscription.apply(command(r#"{"action": "subscribe", "sections": ["network"], "fs_names": ["backups"]}"#)).unwrap();
+        let value = subscription.filter(&stats);
+        assert!(value.get("network_stats").is_some());
+        assert_eq!(filtered_fs_names(&value), vec!["backups"]);
+
+        subscription.apply(command(r#"{"action": "subscribe", "fs_names": ["root", "backups"]}"#)).unwrap();
+        assert_eq!(subscription.fs_names, Some(vec!["backups".to_string(), "root".to_string()]));
+
+        subscription.apply(command(r#"{"action": "unsubscribe", "sections": ["basic"], "fs_names": ["backups"]}"#)).unwrap();
+        let value = subscription.filter(&stats);
+        assert!(value.get("basic_stats").is_none());
+        assert_eq!(filtered_fs_names(&value), vec!["root"]);
+
+        subscription.apply(command(r#"{"action": "set_interval", "secs": 30}"#)).unwrap();
+        assert_eq!(subscription.interval_secs, 30);
+    }
+
+    #[test]
+    fn rejects_unsubscribing_the_last_fs_name() {
+        let mut subscription = subscription(Some("basic,filesystems"), Some("root"), None);
+        let stats = sample_with_filesystems(&["root", "backups"]);
+
+        // The subscription is left as it was
+        assert!(subscription.apply(command(r#"{"action": "unsubscribe", "sections": ["basic"], "fs_names": ["root"]}"#)).is_err());
+        let value = subscription.filter(&stats);
+        assert!(value.get("basic_stats").is_some());
+        assert_eq!(filtered_fs_names(&value), vec!["root"]);
+
+        // Unless the filesystems section goes with it, and a later subscription to the section gets them all
+        subscription.apply(command(r#"{"action": "unsubscribe", "sections": ["filesystems"], "fs_names": ["root"]}"#)).unwrap();
+        assert!(subscription.filter(&stats).get("file_systems_stats").is_none());
+        subscription.apply(command(r#"{"action": "subscribe", "sections": ["filesystems"]}"#)).unwrap();
+        assert_eq!(filtered_fs_names(&subscription.filter(&stats)), vec!["root", "backups"]);
+    }
+}
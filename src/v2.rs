@@ -0,0 +1,273 @@
+// Typed statistics model (served on /v2/get-stats).
+// Values are numbers with the unit in the field name; a value that was not
+// collected (disabled, sensor or mount point not found, API unreachable...)
+// is `null` instead of a made up "0.0".
+use serde::{Deserialize, Serialize};
+
+// ------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Stats {
+    #[serde(default)]
+    pub timestamp: u64,                     // seconds since the unix epoch
+    #[serde(default)]
+    pub sequence: u64,                      // increases by one with every sample, across restarts too
+    #[serde(default)]
+    pub collection_secs: f64,               // time taken to collect the sample
+    #[serde(default)]
+    pub hostname: String,
+    pub basic_stats: BasicStats,
+    pub file_systems_stats: Vec<FileSystemStats>,
+    #[serde(default)]
+    pub network_stats: Vec<NetworkInterfaceStats>,
+    #[serde(default)]
+    pub block_devices_stats: Vec<BlockDeviceStats>,
+    #[serde(default)]
+    pub processes_stats: ProcessesStats,
+    pub kubernetes_stats: Vec<KubernetesStats>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct BasicStats {
+    pub cpu_percent: Option<f64>,                   // average of all the cores
+    pub cpu_cores_percent: Option<Vec<f64>>,        // one value per core
+    pub cpu_user_percent: Option<f64>,              // share of the CPU time spent in each mode since the previous sample
+    pub cpu_nice_percent: Option<f64>,
+    pub cpu_system_percent: Option<f64>,
+    pub cpu_idle_percent: Option<f64>,
+    pub cpu_iowait_percent: Option<f64>,
+    pub cpu_irq_percent: Option<f64>,
+    pub cpu_softirq_percent: Option<f64>,
+    pub cpu_steal_percent: Option<f64>,
+    pub ram_percent: Option<f64>,
+    pub ram_used_bytes: Option<u64>,
+    pub ram_total_bytes: Option<u64>,
+    pub root_fs_percent: Option<f64>,
+    pub root_fs_inodes_total: Option<u64>,
+    pub root_fs_inodes_used: Option<u64>,
+    pub root_fs_inodes_free: Option<u64>,
+    pub root_fs_inodes_percent: Option<f64>,
+    pub swap_percent: Option<f64>,
+    pub swap_used_bytes: Option<u64>,
+    pub swap_total_bytes: Option<u64>,
+    pub net_down_kbps: Option<f64>,
+    pub net_up_kbps: Option<f64>,
+    pub temperature_celsius: Option<f64>,
+    pub load_average_1m: Option<f64>,
+    pub load_average_5m: Option<f64>,
+    pub load_average_15m: Option<f64>,
+    pub uptime_secs: Option<u64>,
+    pub boot_time: Option<u64>,                     // unix timestamp
+    pub processes_total: Option<u64>,
+    pub processes_running: Option<u64>,
+    pub processes_sleeping: Option<u64>,            // interruptible, uninterruptible (disk) and idle (kernel threads) sleep
+    pub processes_zombie: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileSystemStats {
+    pub fs_name: String,
+    pub fs_mount_point: String,                     // mount path, or device as /dev/sdb1, UUID=..., LABEL=...
+    #[serde(default)]
+    pub fs_mounted: Option<bool>,                   // false when nothing is mounted there (the other values are then null)
+    pub fs_used_percent: Option<f64>,
+    #[serde(default)]
+    pub fs_total_bytes: Option<u64>,
+    #[serde(default)]
+    pub fs_used_bytes: Option<u64>,
+    #[serde(default)]
+    pub fs_available_bytes: Option<u64>,
+    #[serde(default)]
+    pub fs_inodes_total: Option<u64>,
+    #[serde(default)]
+    pub fs_inodes_used: Option<u64>,
+    #[serde(default)]
+    pub fs_inodes_free: Option<u64>,
+    #[serde(default)]
+    pub fs_inodes_used_percent: Option<f64>,
+    #[serde(default)]
+    pub fs_type: Option<String>,                    // ext4, xfs, btrfs...
+    #[serde(default)]
+    pub fs_device: Option<String>,                  // /dev/sda1...
+    #[serde(default)]
+    pub fs_read_only: Option<bool>,
+    #[serde(default)]
+    pub fs_removable: Option<bool>,
+    #[serde(default)]
+    pub fs_growth_percent_per_hour: Option<f64>,    // linear regression over the forecast window
+    #[serde(default)]
+    pub fs_hours_to_full: Option<f64>,              // null while the filesystem is not growing
+}
+
+// One of the interfaces selected by `iface`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NetworkInterfaceStats {
+    pub iface: String,
+    pub down_kbps: f64,
+    pub up_kbps: f64,
+    #[serde(default)]
+    pub rx_packets_per_sec: f64,
+    #[serde(default)]
+    pub tx_packets_per_sec: f64,
+    #[serde(default)]
+    pub rx_bytes_total: u64,                        // counters since boot
+    #[serde(default)]
+    pub tx_bytes_total: u64,
+    #[serde(default)]
+    pub rx_packets_total: u64,
+    #[serde(default)]
+    pub tx_packets_total: u64,
+    #[serde(default)]
+    pub rx_errors_total: u64,
+    #[serde(default)]
+    pub tx_errors_total: u64,
+    #[serde(default)]
+    pub rx_dropped_total: Option<u64>,
+    #[serde(default)]
+    pub tx_dropped_total: Option<u64>,
+    #[serde(default)]
+    pub operstate: Option<String>,                  // up, down, dormant, unknown...
+    #[serde(default)]
+    pub link_up: Option<bool>,                      // carrier detected
+    #[serde(default)]
+    pub mtu: Option<u64>,
+    #[serde(default)]
+    pub mac_address: Option<String>,
+    #[serde(default)]
+    pub ip_addresses: Vec<String>,                  // CIDR notation, IPv4 and IPv6
+}
+
+// Rates over the last polling interval, null right after the first poll
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BlockDeviceStats {
+    pub device: String,
+    pub read_bytes_per_sec: Option<f64>,
+    pub write_bytes_per_sec: Option<f64>,
+    pub read_iops: Option<f64>,
+    pub write_iops: Option<f64>,
+    pub queue_time_ms: Option<f64>,                 // average time a request spent queued and being served
+    pub util_percent: Option<f64>,                  // share of the time the device was busy
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ProcessesStats {
+    pub top_cpu: Vec<ProcessStats>,                 // busiest first
+    pub top_memory: Vec<ProcessStats>,              // largest first
+    #[serde(default)]
+    pub watched: Vec<WatchedProcessStats>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProcessStats {
+    pub pid: u32,
+    pub name: String,
+    pub cmdline: String,
+    pub user: Option<String>,
+    pub cpu_percent: f64,                           // of a single core, so up to 100 x cores
+    pub rss_bytes: u64,
+    pub memory_percent: Option<f64>,
+    pub disk_read_bytes_per_sec: Option<f64>,       // null on the first poll of the process
+    pub disk_write_bytes_per_sec: Option<f64>,
+    pub disk_read_bytes_total: u64,
+    pub disk_written_bytes_total: u64,
+}
+
+// Every instance of a watched process taken together
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WatchedProcessStats {
+    pub name: String,
+    pub running: bool,
+    pub count: u64,
+    pub cpu_percent: f64,
+    pub rss_bytes: u64,
+    pub memory_percent: Option<f64>,
+    pub restarts: u64,                              // since the exporter started: all the instances got new pids
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KubernetesStats {
+    pub node_stats: Vec<KubernetesNodeStats>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KubernetesNodeStats {
+    pub node_role: String,
+    pub node_name: String,
+    pub node_ip: String,
+    pub node_basic_stats: BasicStats,
+    pub node_pods: Vec<String>,
+    pub node_pods_max: Option<u64>,
+}
+
+// ------------------------------------------------------------------
+
+impl Stats {
+    // Every numeric value of the host, filesystems, network, block devices and
+    // watched processes sections, named after its path in the sample:
+    // `basic_stats.cpu_percent` for the host,
+    // `file_systems_stats.<fs_name>.fs_used_percent` for each filesystem,
+    // `network_stats.<iface>.down_kbps` for each network interface,
+    // `block_devices_stats.<device>.util_percent` for each block device and
+    // `processes_stats.watched.<name>.count` for each watched process.
+    // Flags count as 1 (true) or 0 (false), lists get one field per item
+    // (`basic_stats.cpu_cores_percent.0`), values that were not collected
+    // are left out.
+    pub fn numeric_fields(&self) -> Vec<(String, f64)> {
+        let mut fields: Vec<(String, f64)> = Vec::new();
+        push_numeric_fields(&mut fields, "basic_stats", &self.basic_stats);
+        for fs in &self.file_systems_stats {
+            push_numeric_fields(&mut fields, &format!("file_systems_stats.{}", fs.fs_name), fs);
+        }
+        for iface in &self.network_stats {
+            push_numeric_fields(&mut fields, &format!("network_stats.{}", iface.iface), iface);
+        }
+        for device in &self.block_devices_stats {
+            push_numeric_fields(&mut fields, &format!("block_devices_stats.{}", device.device), device);
+        }
+        for process in &self.processes_stats.watched {
+            push_numeric_fields(&mut fields, &format!("processes_stats.watched.{}", process.name), process);
+        }
+        fields
+    }
+}
+
+fn push_numeric_fields<T: Serialize>(fields: &mut Vec<(String, f64)>, prefix: &str, section: &T) {
+    if let Ok(serde_json::Value::Object(map)) = serde_json::to_value(section) {
+        for (key, value) in map {
+            let number = match value {
+                serde_json::Value::Bool(flag) => Some(if flag { 1. } else { 0. }),
+                serde_json::Value::Array(items) => {
+                    for (index, item) in items.iter().enumerate() {
+                        if let Some(number) = item.as_f64() {
+                            fields.push((format!("{}.{}.{}", prefix, key, index), number));
+                        }
+                    }
+                    None
+                }
+                other => other.as_f64(),
+            };
+            if let Some(number) = number {
+                fields.push((format!("{}.{}", prefix, key), number));
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------
+
+// A sample with only the host RAM usage, for the tests of the modules working on samples
+#[cfg(test)]
+pub(crate) fn sample(timestamp: u64, ram_percent: f64) -> Stats {
+    Stats {
+        timestamp,
+        sequence: 0,
+        collection_secs: 0.,
+        hostname: String::from("vm"),
+        basic_stats: BasicStats { ram_percent: Some(ram_percent), ..Default::default() },
+        file_systems_stats: Vec::new(),
+        network_stats: Vec::new(),
+        block_devices_stats: Vec::new(),
+        processes_stats: ProcessesStats::default(),
+        kubernetes_stats: Vec::new(),
+    }
+}